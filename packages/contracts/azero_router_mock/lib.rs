@@ -0,0 +1,54 @@
+#![cfg_attr(not(feature = "std"), no_std, no_main)]
+pub use self::azero_router_mock::{AzeroRouterMockRef, DOMAIN_NOT_FOUND, GET_ADDRESS_SELECTOR};
+
+/// Stand-in for the AZERO.ID router, exposing `get_address` under the router's selector so
+/// pizza_tipper's domain resolution can be exercised without a live chain.
+#[ink::contract]
+mod azero_router_mock {
+    use ink::{prelude::string::String, storage::Mapping};
+    pub const GET_ADDRESS_SELECTOR: [u8; 4] = [0xd2, 0x59, 0xf7, 0xba];
+    /// Error code returned by `get_address` for unregistered names.
+    pub const DOMAIN_NOT_FOUND: u8 = 0;
+
+    #[ink(storage)]
+    pub struct AzeroRouterMock {
+        addresses: Mapping<String, AccountId>,
+    }
+
+    impl AzeroRouterMock {
+        #[ink(constructor)]
+        pub fn new() -> Self {
+            Self {
+                addresses: Mapping::default(),
+            }
+        }
+
+        /// Points `domain` (e.g. `"pizza.azero"`) at `address`.
+        #[ink(message)]
+        pub fn register(&mut self, domain: String, address: AccountId) {
+            self.addresses.insert(domain, &address);
+        }
+
+        #[ink(message, selector = 0xd259f7ba)]
+        pub fn get_address(&self, domain: String) -> Result<AccountId, u8> {
+            self.addresses.get(domain).ok_or(DOMAIN_NOT_FOUND)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[ink::test]
+        fn registered_domains_resolve() {
+            let accts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut router = AzeroRouterMock::new();
+            router.register("pizza.azero".into(), accts.bob);
+            assert_eq!(router.get_address("pizza.azero".into()), Ok(accts.bob));
+            assert_eq!(
+                router.get_address("pasta.azero".into()),
+                Err(DOMAIN_NOT_FOUND)
+            );
+        }
+    }
+}
@@ -1,21 +1,54 @@
 #![cfg_attr(not(feature = "std"), no_std, no_main)]
 
-pub use self::highlighted_pizzas::{
-    HighlightedPizzasError, HighlightedPizzasRef, DELETE_PIZZA_SELECTOR, GET_BY_AUTHOR_SELECTOR,
-    HIGHLIGHTED_CONTENT_SELECTOR, HIGHLIGHTED_PIZZA_SELECTOR, HIGHLIGHT_CONTENT_SELECTOR,
-    HIGHLIGHT_PIZZA_SELECTOR,
-};
+use ink::primitives::AccountId;
+
+pub use self::highlighted_pizzas::HighlightedPizzasRef;
+
+#[derive(Debug, PartialEq, Eq, scale::Encode, scale::Decode)]
+#[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+pub enum HighlightedPizzasError {
+    AlreadyHighlighted,
+    HighlightNotFound,
+    AccessDenied,
+}
+
+/// Interface shared with pizza_tipper, which calls it through `ink::contract_ref!`.
+#[ink::trait_definition]
+pub trait HighlightPizzas {
+    #[ink(message, payable)]
+    fn add(
+        &mut self,
+        from: AccountId,
+        to: AccountId,
+        id: u32,
+        pizzas: u32,
+    ) -> Result<(), HighlightedPizzasError>;
+
+    #[ink(message, payable)]
+    fn add_content(&mut self, author: AccountId, id: u32) -> Result<(), HighlightedPizzasError>;
+
+    #[ink(message)]
+    fn get_highlighted_pizzas(&self, from: AccountId) -> Option<u32>;
+
+    #[ink(message)]
+    fn get_content_by_author(&self, author: AccountId) -> Option<u32>;
+
+    #[ink(message)]
+    fn delete_tip_by_author(&mut self, from: AccountId) -> Result<(), HighlightedPizzasError>;
+
+    #[ink(message)]
+    fn delete_content_by_author(&mut self, author: AccountId)
+        -> Result<(), HighlightedPizzasError>;
+
+    /// Account that instantiated this contract, the only one allowed to change it.
+    #[ink(message)]
+    fn created_by(&self) -> AccountId;
+}
 
 #[ink::contract]
 mod highlighted_pizzas {
+    use super::{HighlightPizzas, HighlightedPizzasError};
     use ink::{codegen::EmitEvent, prelude::vec::Vec, storage::Mapping};
-    pub const HIGHLIGHTED_PIZZA_SELECTOR: [u8; 4] = [0, 0, 0, 6];
-    pub const HIGHLIGHT_PIZZA_SELECTOR: [u8; 4] = [0, 0, 0, 7];
-    pub const HIGHLIGHTED_CONTENT_SELECTOR: [u8; 4] = [0, 0, 0, 8];
-    pub const DELETE_PIZZA_SELECTOR: [u8; 4] = [0, 0, 0, 4];
-    pub const DELETE_CONTENT_SELECTOR: [u8; 4] = [0, 0, 0, 3];
-    pub const HIGHLIGHT_CONTENT_SELECTOR: [u8; 4] = [0, 0, 0, 9];
-    pub const GET_BY_AUTHOR_SELECTOR: [u8; 4] = [0, 0, 0, 5];
     type Event = <HighlightedPizzas as ink::reflect::ContractEventBase>::Type;
 
     #[ink(event)]
@@ -36,14 +69,6 @@ mod highlighted_pizzas {
         pizzas: u32,
     }
 
-    #[derive(Debug, PartialEq, Eq, scale::Encode, scale::Decode)]
-    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
-    pub enum HighlightedPizzasError {
-        AlreadyHighlighted,
-        HighlightNotFound,
-        AccessDenied,
-    }
-
     #[ink(storage)]
     pub struct HighlightedPizzas {
         created_by: AccountId,
@@ -67,8 +92,17 @@ mod highlighted_pizzas {
             }
         }
 
-        #[ink(message, payable, selector = 7)]
-        pub fn add(
+        fn emit_event<EE>(emmiter: EE, event: Event)
+        where
+            EE: EmitEvent<HighlightedPizzas>,
+        {
+            emmiter.emit_event(event);
+        }
+    }
+
+    impl HighlightPizzas for HighlightedPizzas {
+        #[ink(message, payable)]
+        fn add(
             &mut self,
             from: AccountId,
             to: AccountId,
@@ -95,8 +129,8 @@ mod highlighted_pizzas {
                 Ok(())
             }
         }
-        #[ink(message, payable, selector = 9)]
-        pub fn add_content(
+        #[ink(message, payable)]
+        fn add_content(
             &mut self,
             author: AccountId,
             id: u32,
@@ -117,23 +151,16 @@ mod highlighted_pizzas {
             }
         }
 
-        fn emit_event<EE>(emmiter: EE, event: Event)
-        where
-            EE: EmitEvent<HighlightedPizzas>,
-        {
-            emmiter.emit_event(event);
-        }
-
-        #[ink(message, selector = 6)]
-        pub fn get_highlighted_pizzas(&self, from: AccountId) -> Option<u32> {
+        #[ink(message)]
+        fn get_highlighted_pizzas(&self, from: AccountId) -> Option<u32> {
             self.highlighted_pizzas.get(from)
         }
-        #[ink(message, selector = 8)]
-        pub fn get_content_by_author(&self, author: AccountId) -> Option<u32> {
+        #[ink(message)]
+        fn get_content_by_author(&self, author: AccountId) -> Option<u32> {
             self.highlighted_content.get(author)
         }
-        #[ink(message, selector = 4)]
-        pub fn delete_tip_by_author(
+        #[ink(message)]
+        fn delete_tip_by_author(
             &mut self,
             from: AccountId,
         ) -> Result<(), HighlightedPizzasError> {
@@ -152,8 +179,8 @@ mod highlighted_pizzas {
                 Ok(())
             }
         }
-        #[ink(message, selector = 3)]
-        pub fn delete_content_by_author(
+        #[ink(message)]
+        fn delete_content_by_author(
             &mut self,
             author: AccountId,
         ) -> Result<(), HighlightedPizzasError> {
@@ -174,10 +201,10 @@ mod highlighted_pizzas {
             }
         }
 
-        // #[ink(message)]
-        // pub fn created_by(&self) -> AccountId {
-        //     self.created_by
-        // }
+        #[ink(message)]
+        fn created_by(&self) -> AccountId {
+            self.created_by
+        }
     }
 
     /// Unit tests in Rust are normally defined within such a `#[cfg(test)]`
@@ -185,7 +212,47 @@ mod highlighted_pizzas {
     /// The below code is technically just normal Rust code.
     #[cfg(test)]
     mod tests {
-        
+        use super::*;
+
+        fn set_caller(caller: AccountId) {
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(caller);
+        }
+
+        #[ink::test]
+        fn only_creator_can_highlight() {
+            let accts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            set_caller(accts.alice);
+            let mut highlighted = HighlightedPizzas::new();
+            assert_eq!(highlighted.created_by(), accts.alice);
+            set_caller(accts.bob);
+            assert_eq!(
+                highlighted.add(accts.bob, accts.charlie, 0, 1),
+                Err(HighlightedPizzasError::AccessDenied)
+            );
+            assert_eq!(
+                highlighted.delete_tip_by_author(accts.bob),
+                Err(HighlightedPizzasError::AccessDenied)
+            );
+        }
+
+        #[ink::test]
+        fn highlight_and_remove_tip() {
+            let accts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            set_caller(accts.alice);
+            let mut highlighted = HighlightedPizzas::new();
+            assert_eq!(highlighted.add(accts.bob, accts.charlie, 4, 2), Ok(()));
+            assert_eq!(
+                highlighted.add(accts.bob, accts.charlie, 5, 1),
+                Err(HighlightedPizzasError::AlreadyHighlighted)
+            );
+            assert_eq!(highlighted.get_highlighted_pizzas(accts.bob), Some(4));
+            assert_eq!(highlighted.delete_tip_by_author(accts.bob), Ok(()));
+            assert_eq!(highlighted.get_highlighted_pizzas(accts.bob), None);
+            assert_eq!(
+                highlighted.delete_tip_by_author(accts.bob),
+                Err(HighlightedPizzasError::HighlightNotFound)
+            );
+        }
     }
 
     /// This is how you'd write end-to-end (E2E) or integration tests for ink! contracts.
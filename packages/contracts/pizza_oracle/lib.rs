@@ -1,14 +1,12 @@
 #![cfg_attr(not(feature = "std"), no_std, no_main)]
-pub use pizza_oracle::{OracleQueried, OracleError, GET_PIZZA_PRICE_SELECTOR, PizzaOracle, PizzaPrice, PizzaOracleRef};
+pub use self::pizza_oracle::{OracleQueried, OracleError, GET_PIZZA_PRICE_SELECTOR, PizzaOracle, PizzaPrice, PizzaOracleRef};
 
 #[ink::contract]
 mod pizza_oracle {
 
-    use ink::{codegen::EmitEvent, prelude::vec::Vec, storage::Mapping, env::AccountIdGuard};
+    use ink::storage::Mapping;
     pub const GET_PIZZA_PRICE_SELECTOR: [u8; 4] = [0,0,0,5]; 
 
-    type Event = <PizzaOracle as ink::reflect::ContractEventBase>::Type;
-
     #[ink(event)]
     pub struct OracleQueried {
         from: AccountId,
@@ -37,11 +35,16 @@ mod pizza_oracle {
         feature = "std",
         derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
     )]
+    pub struct PizzaPrice {
+        pub confidence: u64,
+        pub current_pizza_price: u128,
 
-       pub struct PizzaPrice {
-        confidence: u64,
-        current_pizza_price: u128,
+    }
 
+    impl Default for PizzaOracle {
+        fn default() -> Self {
+            Self::new()
+        }
     }
 
     impl PizzaOracle {
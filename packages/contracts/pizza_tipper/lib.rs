@@ -2,21 +2,23 @@
 
 #[ink::contract]
 mod tipper {
-    use highlighted_pizzas::{
-        HighlightedPizzasError, HighlightedPizzasRef, HIGHLIGHT_PIZZA_SELECTOR,
-    };
+    use highlighted_pizzas::{HighlightPizzas, HighlightedPizzasError, HighlightedPizzasRef};
+    use pizza_oracle::{PizzaPrice, GET_PIZZA_PRICE_SELECTOR};
      
    
     use ink::reflect::ContractEventBase;
     use ink::{
-        codegen::EmitEvent,
+        codegen::{EmitEvent, TraitCallBuilder},
+        contract_ref,
         env::{
-            call::{build_call, ExecutionInput, FromAccountId, Selector},
+            call::{build_call, ExecutionInput, Selector},
+            hash::Blake2x256,
             DefaultEnvironment,
         },
         prelude::{string::String, vec::Vec},
-        storage::Mapping,
-        LangError,
+        primitives::Key,
+        storage::{traits::StorageKey, Lazy, Mapping},
+        ToAccountId,
     };
     // use tracing::Event;
     
@@ -30,24 +32,320 @@ mod tipper {
         to: AccountId,
         pizzas: u32,
         message: String,
-        //Payment channel: amount withdrawn by 'to'
+        //post of `to` this tip is for
+        content_id: Option<u32>,
+    }
+
+    /// `Tip` layout of storage versions 0 and 1, before tips referenced posts.
+    #[derive(scale::Encode, scale::Decode)]
+    struct TipV0 {
+        from: AccountId,
+        to: AccountId,
+        pizzas: u32,
+        message: String,
+    }
+
+    impl From<TipV0> for Tip {
+        fn from(tip: TipV0) -> Self {
+            Self {
+                from: tip.from,
+                to: tip.to,
+                pizzas: tip.pizzas,
+                message: tip.message,
+                content_id: None,
+            }
+        }
+    }
+
+    /// Public, read-only representation of a stored tip.
+    #[derive(Debug, Clone, PartialEq, Eq, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct TipView {
+        pub id: u32,
+        pub from: AccountId,
+        pub to: AccountId,
+        pub pizzas: u32,
+        pub message: String,
+        pub content_id: Option<u32>,
+        pub visibility: Visibility,
+        /// The recipient's reply, if any.
+        pub reply: Option<String>,
+    }
+
+    impl TipView {
+        fn new(id: u32, tip: Tip, visibility: Visibility, reply: Option<String>) -> Self {
+            Self {
+                id,
+                from: tip.from,
+                to: tip.to,
+                pizzas: tip.pizzas,
+                // hidden messages are kept in storage so the creator can unhide them
+                message: if visibility == Visibility::Hidden {
+                    String::new()
+                } else {
+                    tip.message
+                },
+                content_id: tip.content_id,
+                visibility,
+                reply,
+            }
+        }
+
+        /// Whether the tip is shown in listings such as `tips_for_creator`.
+        fn is_listed(&self) -> bool {
+            matches!(self.visibility, Visibility::Visible | Visibility::Redacted)
+        }
+    }
+
+    /// Visibility of a tip as returned by queries. The payment record is kept in every state.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum Visibility {
+        Visible,
+        /// Hidden from listings by the recipient; the message is kept.
+        Hidden,
+        /// Message removed by a moderator; the tip is still listed.
+        Redacted,
+        /// Message removed by a moderator and the tip left out of listings.
+        Deleted,
+    }
+
+    /// Action taken on a tip by a moderator. Hiding by the recipient is tracked apart, so
+    /// a hidden tip stays hidden when it is redacted.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub enum Moderation {
+        Redacted,
+        Deleted,
+    }
+    /// Content published by a creator; the content itself lives off-chain at `uri`.
+    #[derive(Debug, Clone, PartialEq, Eq, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct Post {
+        pub id: u32,
+        pub author: AccountId,
+        pub uri: String,
+        pub content_hash: Hash,
+        pub title: String,
+        pub posted_at: Timestamp,
+    }
+
+    /// Public profile of a registered creator.
+    #[derive(Debug, Clone, PartialEq, Eq, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct Creator {
+        pub account: AccountId,
+        pub handle: String,
+        pub profile_uri: String,
+        /// Account credited with the tips sent to `account`.
+        pub payout_account: AccountId,
+    }
+
+    /// Prepaid recurring tip: `pizzas_per_period` pizzas accrue to `creator` at the end of
+    /// every `period_ms`, for `periods` periods.
+    #[derive(Debug, Clone, PartialEq, Eq, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct Subscription {
+        pub id: u32,
+        pub subscriber: AccountId,
+        pub creator: AccountId,
+        pub pizzas_per_period: u32,
+        pub period_ms: u64,
+        pub periods: u32,
+        /// Cost of one period, fixed when subscribing.
+        pub price_per_period: Balance,
+        pub started_at: Timestamp,
+        /// Periods already paid out to the creator.
+        pub claimed_periods: u32,
+        pub cancelled: bool,
+    }
+
+    /// Unidirectional payment channel from `tipper` to `creator`. The tipper signs vouchers
+    /// for a cumulative amount off-chain with the ECDSA key `signer`, and the creator
+    /// redeems the latest one.
+    ///
+    /// `signer` is the account of that key, i.e. the blake2-256 hash of the compressed
+    /// public key, as for any ECDSA Substrate account.
+    #[derive(Debug, Clone, PartialEq, Eq, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct Channel {
+        pub id: u32,
+        pub tipper: AccountId,
+        pub creator: AccountId,
+        pub signer: AccountId,
+        pub deposit: Balance,
+        /// Cumulative amount paid out to the creator so far.
+        pub redeemed: Balance,
+        pub expires_at: Timestamp,
+        /// End of the challenge window started by `start_channel_close`.
+        pub closing_at: Option<Timestamp>,
+    }
+
+    /// Roles that can be granted on top of ownership; the owner implicitly holds all of them.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub enum Role {
+        Admin,
+        PriceSetter,
+        Moderator,
+    }
+
+    /// Operations that can be paused independently of each other.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, scale::Encode, scale::Decode)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub enum PauseScope {
+        Tipping,
+        Highlighting,
+        Withdrawals,
     }
+
     pub type ResolveResult<AccountId> = Result<AccountId, TipperError>;
+    /// Tippers with their pizza totals, highest first.
+    pub type Leaderboard = Vec<(AccountId, u64)>;
+
+    /// Selector of `get_address(domain: String) -> Result<AccountId, u8>` on the AZERO.ID router.
+    pub const AZERO_ID_GET_ADDRESS_SELECTOR: [u8; 4] = [0xd2, 0x59, 0xf7, 0xba];
+
+    /// Upper bound on the number of entries returned by a paginated query.
+    pub const MAX_PAGE_SIZE: u32 = 50;
+    /// Time the creator has to redeem a voucher once the tipper starts closing a channel.
+    pub const CHANNEL_CHALLENGE_PERIOD_MS: Timestamp = 24 * 60 * 60 * 1000;
+    /// `max_message_len` of a new deployment, in bytes.
+    pub const DEFAULT_MAX_MESSAGE_LEN: u32 = 280;
+    /// `max_batch_size` of a new deployment.
+    pub const DEFAULT_MAX_BATCH_SIZE: u32 = 20;
+    /// Number of entries kept in each top-tipper leaderboard.
+    pub const LEADERBOARD_SIZE: usize = 10;
+    /// Maximum number of recipients of a single `tip_split`.
+    pub const MAX_SPLIT_RECIPIENTS: u32 = 16;
+    /// Maximum length of a creator handle, in bytes. Handles are stored lowercased and may
+    /// only contain ASCII letters, digits, `_`, `-` and `.`.
+    pub const MAX_HANDLE_LEN: usize = 32;
+    /// Maximum length of a post or profile URI, in bytes.
+    pub const MAX_URI_LEN: usize = 256;
+    /// Maximum length of a post title, in bytes.
+    pub const MAX_TITLE_LEN: usize = 128;
+    /// Denominator of `fee_bps`: a fee of `BPS_DENOMINATOR` takes the whole payment.
+    pub const BPS_DENOMINATOR: u16 = 10_000;
+    /// Storage layout version; bumped whenever a stored record such as `Tip` changes.
+    ///
+    /// Every field of `Tipper` lives in its own `Lazy` or `Mapping` cell, so the root
+    /// struct encodes to nothing and fields added later decode as unset after `upgrade`.
+    /// `migrate` rewrites records of older versions in place.
+    pub const STORAGE_VERSION: u8 = 2;
 
     #[ink(storage)]
     #[derive(Default)]
     pub struct Tipper {
-        id_counter: u32,
-        id_map: Mapping<AccountId, u32>,
+        id_counter: Lazy<u32>,
+        //(tipper, nth tip of tipper) -> tip id
+        id_map: Mapping<(AccountId, u32), u32>,
+        tip_counts: Mapping<AccountId, u32>,
+        //(creator, nth tip received) -> tip id
+        received_map: Mapping<(AccountId, u32), u32>,
+        received_counts: Mapping<AccountId, u32>,
         tip_map: Mapping<u32, Tip>,
-        elements_count: u32,
-        pizza_tippers: Vec<AccountId>,
+        elements_count: Lazy<u32>,
+        //every account that ever tipped, once
+        pizza_tippers: Lazy<Vec<AccountId>>,
+        //pizza totals and top tippers, by pizzas sent, highest first
+        pizzas_sent: Mapping<AccountId, u64>,
+        //(tipper, creator) -> pizzas
+        pizzas_to_creator: Mapping<(AccountId, AccountId), u64>,
+        creator_leaderboards: Mapping<AccountId, Leaderboard>,
+        global_leaderboard: Lazy<Leaderboard>,
+        //tip messages: maximum length in bytes and storage surcharge per byte
+        max_message_len: Lazy<u32>,
+        message_byte_fee: Lazy<Balance>,
+        //moderation: tips absent from tip_visibility are unmoderated
+        tip_visibility: Mapping<u32, Moderation>,
+        //tips hidden from listings by their recipient
+        hidden_tips: Mapping<u32, ()>,
+        //(tip, reporter) -> ()
+        tip_reports: Mapping<(u32, AccountId), ()>,
+        report_counts: Mapping<u32, u32>,
+        //tip id -> reply of the recipient
+        tip_replies: Mapping<u32, String>,
+        //maximum number of entries of a `tip_batch`
+        max_batch_size: Lazy<u32>,
+        //split tips: tip id -> group id, group id -> (first tip id, number of tips)
+        split_counter: Lazy<u32>,
+        tip_groups: Mapping<u32, u32>,
+        split_ranges: Mapping<u32, (u32, u32)>,
+        //subscriptions: (subscriber, nth) and (creator, nth) -> subscription id
+        subscription_counter: Lazy<u32>,
+        subscriptions: Mapping<u32, Subscription>,
+        subscriber_subs: Mapping<(AccountId, u32), u32>,
+        subscriber_sub_counts: Mapping<AccountId, u32>,
+        creator_subs: Mapping<(AccountId, u32), u32>,
+        creator_sub_counts: Mapping<AccountId, u32>,
+        //prepaid and not yet accrued
+        subscription_escrow: Lazy<Balance>,
+        //payment channels
+        channel_counter: Lazy<u32>,
+        channels: Mapping<u32, Channel>,
+        channel_escrow: Lazy<Balance>,
         //oracle -> pizza cost goodness
-        price_per_pizza: u128,
-        pizza_oracle: Option<AccountId>,
+        price_per_pizza: Lazy<u128>,
+        pizza_oracle: Lazy<Option<AccountId>>,
+        oracle_price_id: Lazy<u32>,
+        min_oracle_confidence: Lazy<u64>,
+        //AZERO.ID router used to resolve domains to accounts
+        azero_router: Lazy<Option<AccountId>>,
         //highlighted tips and creator posts :
-        highlighted_pizzas: Option<AccountId>,
-
+        highlighted_pizzas: Lazy<Option<AccountId>>,
+        highlight_price: Lazy<Balance>,
+        post_counter: Lazy<u32>,
+        post_map: Mapping<u32, Post>,
+        //(author, nth post) -> post id
+        author_posts: Mapping<(AccountId, u32), u32>,
+        author_post_counts: Mapping<AccountId, u32>,
+        pizzas_per_post: Mapping<u32, u64>,
+        //(post, nth tip for post) -> tip id
+        post_tips: Mapping<(u32, u32), u32>,
+        post_tip_counts: Mapping<u32, u32>,
+        //creator registry
+        creators: Mapping<AccountId, Creator>,
+        //handle -> creator
+        handles: Mapping<String, AccountId>,
+        //pull-based payouts: tips are credited here and withdrawn by the recipient
+        earnings: Mapping<AccountId, Balance>,
+        outstanding_earnings: Lazy<Balance>,
+        //protocol fee taken from every tip, in basis points
+        fee_bps: Lazy<u16>,
+        treasury: Lazy<AccountId>,
+        treasury_balance: Lazy<Balance>,
+        //access control
+        owner: Lazy<AccountId>,
+        pending_owner: Lazy<Option<AccountId>>,
+        roles: Mapping<(AccountId, Role), ()>,
+        //circuit breaker
+        paused: Lazy<Vec<PauseScope>>,
+        //upgrades
+        version: Lazy<u8>,
+        storage_version: Lazy<u8>,
+        migration_cursor: Lazy<u32>,
     }
 
     #[derive(Debug, PartialEq, Eq, scale::Encode, scale::Decode)]
@@ -59,287 +357,3413 @@ mod tipper {
         HighlightError(HighlightedPizzasError),
         //Error for azero resolver
         DoesntExist,
+        ResolverUnavailable,
+        /// Requested withdrawal exceeds the caller's earnings, which are returned.
+        InsufficientEarnings(Balance),
+        NoEarnings,
+        TransferFailed,
+        Overflow,
+        InvalidFee,
+        Unauthorized,
+        MissingRole(Role),
+        Paused,
+        UpgradeFailed,
+        MigrationPending,
+        MigrationFailed(u32),
+        //oracle pricing
+        OracleUnavailable,
+        OraclePriceNotFound,
+        OracleConfidenceTooLow(u64),
+        //highlights
+        TipNotFound,
+        NotTipAuthor,
+        HighlightUnavailable,
+        //creator posts
+        PostNotFound,
+        NotPostAuthor,
+        /// The post exists but was not published by the tipped account.
+        PostNotByRecipient,
+        /// The post URI is empty, too long or contains whitespace or control characters,
+        /// or its title is too long or contains control characters.
+        InvalidPost,
+        //creator registry
+        AlreadyRegistered,
+        HandleTaken,
+        InvalidHandle,
+        /// The profile URI is too long or contains whitespace or control characters.
+        InvalidProfileUri,
+        CreatorNotFound,
+        /// Split recipients are empty, too many, duplicated or have a zero weight.
+        InvalidSplit,
+        /// The message is longer than the returned `max_message_len`.
+        MessageTooLong(u32),
+        /// The message contains control characters.
+        InvalidMessage,
+        //moderation
+        NotTipRecipient,
+        /// The tip's current visibility doesn't allow this moderation action.
+        InvalidVisibilityChange,
+        AlreadyReported,
+        AlreadyReplied,
+        EmptyBatch,
+        /// The batch has more entries than the returned `max_batch_size`.
+        BatchTooLarge(u32),
+        //subscriptions
+        InvalidSubscription,
+        SubscriptionNotFound,
+        NotSubscriber,
+        NotSubscribedCreator,
+        SubscriptionCancelled,
+        NothingToClaim,
+        //payment channels
+        InvalidChannel,
+        ChannelNotFound,
+        NotChannelTipper,
+        NotChannelCreator,
+        /// The channel expired or its challenge window ended.
+        ChannelExpired,
+        /// The channel can't be reclaimed before it expires or its challenge window ends.
+        ChannelStillOpen,
+        /// The voucher signature is invalid or its amount was already redeemed.
+        InvalidVoucher,
+        VoucherExceedsDeposit,
     }
 
     type Event = <Tipper as ContractEventBase>::Type;
     impl Tipper {
+        /// Deploys a Tipper. With `highlighted_pizzas_hash` set, a HighlightedPizzas
+        /// instance is created from that code (salted with this contract's address) and
+        /// owned by this Tipper; otherwise one can be attached later.
         #[ink(constructor)]
         pub fn new(
-            _version: u8,
+            version: u8,
             // _pizza_oracle_hash: Hash,
-            _highlighted_pizzas_hash: Hash,
+            highlighted_pizzas_hash: Option<Hash>,
             price_per_pizza: u128,
+            fee_bps: u16,
+            treasury: AccountId,
         ) -> Self {
-            let _caller = Self::env().caller();
+            assert!(fee_bps <= BPS_DENOMINATOR, "fee exceeds 100%");
             //TODO: cross contract refs
             //let pizza_oracle_ref = PizzaOracleRef::new();
-            let _highlighted_pizzas_ref = HighlightedPizzasRef::new();
-            Self {
-                id_counter: 0,
-                elements_count: 0,
-                price_per_pizza,
-                tip_map: Mapping::default(),
-                id_map: Mapping::default(),
-                pizza_oracle: None,
-                highlighted_pizzas: None,
-                pizza_tippers: Vec::new(),
-            }
+            let highlighted_pizzas = highlighted_pizzas_hash.map(|code_hash| {
+                let highlighted_pizzas_ref: HighlightedPizzasRef = HighlightedPizzasRef::new()
+                    .code_hash(code_hash)
+                    .endowment(0)
+                    .salt_bytes(Self::env().account_id())
+                    .instantiate();
+                highlighted_pizzas_ref.to_account_id()
+            });
+            Self::init(version, highlighted_pizzas, price_per_pizza, fee_bps, treasury)
         }
 
         #[ink(constructor)]
         pub fn free() -> Self {
-            Self {
-                id_counter: 0,
-                price_per_pizza: 0,
-                elements_count: 0,
-                tip_map: Mapping::default(),
-                id_map: Mapping::default(),
-                pizza_tippers: Vec::new(),
-                pizza_oracle: None,
-                highlighted_pizzas: None,
-            }
+            Self::init(0, None, 0, 0, Self::env().caller())
         }
 
+        fn init(
+            version: u8,
+            highlighted_pizzas: Option<AccountId>,
+            price_per_pizza: u128,
+            fee_bps: u16,
+            treasury: AccountId,
+        ) -> Self {
+            let mut instance = Self::default();
+            instance.version.set(&version);
+            instance.storage_version.set(&STORAGE_VERSION);
+            instance.owner.set(&Self::env().caller());
+            instance.highlighted_pizzas.set(&highlighted_pizzas);
+            instance.price_per_pizza.set(&price_per_pizza);
+            instance.fee_bps.set(&fee_bps);
+            instance.treasury.set(&treasury);
+            instance.max_message_len.set(&DEFAULT_MAX_MESSAGE_LEN);
+            instance.max_batch_size.set(&DEFAULT_MAX_BATCH_SIZE);
+            instance
+        }
 
         #[ink(message)]
-        pub fn get_pizza_tippers(&self) -> Vec<AccountId> {
-            self.pizza_tippers.clone()
+        pub fn owner(&self) -> AccountId {
+            // set by every constructor
+            self.owner.get().expect("owner is set on instantiation")
         }
 
-        pub fn lookup_pizzas(&self) -> Result<(), TipperError> {
-            Ok(())
+        #[ink(message)]
+        pub fn pending_owner(&self) -> Option<AccountId> {
+            self.pending_owner.get_or_default()
         }
 
-
-        #[ink(message, payable)]
-        pub fn tip(
-            &mut self,
-            tip_message: String,
-            to: AccountId,
-            n_pizzas: u32,
-        ) -> Result<(), TipperError> {
-            let from = Self::env().caller();
-            ink::env::debug_println!(
-                "{:?} wants to tip {:?} with {:?} pizzas with the message '{:?}' ",
-                from,
-                to,
-                n_pizzas,
-                tip_message
+        /// Proposes `new_owner`; ownership only moves once they call `accept_ownership`.
+        #[ink(message)]
+        pub fn transfer_ownership(&mut self, new_owner: AccountId) -> Result<(), TipperError> {
+            self.ensure_owner()?;
+            self.pending_owner.set(&Some(new_owner));
+            Self::emit_event(
+                Self::env(),
+                Event::OwnershipTransferStarted(OwnershipTransferStarted {
+                    previous_owner: self.owner(),
+                    new_owner,
+                }),
             );
-            // if self.id_map.contains(from) {
-            //     return Err(Error::AlreadyTipped);
-            // }
-            let transfered_amount = self.env().transferred_value();
-            let pizza_cost = self
-                .price_per_pizza
-                .checked_mul(n_pizzas.into())
-                .unwrap_or(u128::MAX);
+            Ok(())
+        }
 
-            if transfered_amount < pizza_cost {
-                return Err(TipperError::InsufficientAmount(pizza_cost));
+        #[ink(message)]
+        pub fn accept_ownership(&mut self) -> Result<(), TipperError> {
+            let caller = self.env().caller();
+            if self.pending_owner() != Some(caller) {
+                return Err(TipperError::Unauthorized);
             }
-            self.env()
-                .transfer(to, transfered_amount)
-                .map_err(|_| TipperError::TipError)?;
-            let event = self._tip(tip_message, from, to, n_pizzas);
-            Self::emit_event(Self::env(), Event::PizzaSent(event));
+            let previous_owner = self.owner();
+            self.owner.set(&caller);
+            self.pending_owner.set(&None);
+            Self::emit_event(
+                Self::env(),
+                Event::OwnershipTransferred(OwnershipTransferred {
+                    previous_owner,
+                    new_owner: caller,
+                }),
+            );
             Ok(())
         }
 
-        fn _tip(
-            &mut self,
-            tip_message: String,
-            from: AccountId,
-            to: AccountId,
-            n_pizzas: u32,
-        ) -> PizzaSent {
-            let tip = Tip {
-                from,
-                to,
-                pizzas: n_pizzas,
-                message: tip_message,
-            };
-            let tip_id = self.insert_tip(&from, tip);
+        #[ink(message)]
+        pub fn has_role(&self, role: Role, account: AccountId) -> bool {
+            account == self.owner() || self.roles.contains((account, role))
+        }
 
-            PizzaSent {
-                from,
-                to,
-                pizzas: n_pizzas,
-                id: tip_id,
-            }
+        /// Grants `role` to `account`. Admins manage the other roles; only the owner manages admins.
+        #[ink(message)]
+        pub fn grant_role(&mut self, role: Role, account: AccountId) -> Result<(), TipperError> {
+            self.ensure_role_manager(role)?;
+            self.roles.insert((account, role), &());
+            Self::emit_event(
+                Self::env(),
+                Event::RoleGranted(RoleGranted {
+                    role,
+                    account,
+                    by: self.env().caller(),
+                }),
+            );
+            Ok(())
         }
 
-        fn insert_tip(&mut self, from: &AccountId, tip: Tip) -> u32 {
-            let pizza_id = self.id_counter;
-            self.id_map.insert(from, &pizza_id);
-            self.tip_map.insert(pizza_id, &tip);
-            self.id_counter = pizza_id + 1;
-            self.pizza_tippers.push(*from);
-            pizza_id
+        #[ink(message)]
+        pub fn revoke_role(&mut self, role: Role, account: AccountId) -> Result<(), TipperError> {
+            self.ensure_role_manager(role)?;
+            self.roles.remove((account, role));
+            Self::emit_event(
+                Self::env(),
+                Event::RoleRevoked(RoleRevoked {
+                    role,
+                    account,
+                    by: self.env().caller(),
+                }),
+            );
+            Ok(())
         }
-        fn reimburse(&self, to: AccountId, amount: u128) {
-            if Self::env().transfer(to, amount).is_err() {
-                panic!("failed to reimburse caller")
+
+        fn ensure_owner(&self) -> Result<(), TipperError> {
+            if self.env().caller() != self.owner() {
+                return Err(TipperError::Unauthorized);
             }
+            Ok(())
         }
-        fn get_by_account(&self, from: &AccountId) -> Option<Tip> {
-            if let Some(tip_id) = self.id_map.get(from) {
-                let tip = self
-                    .tip_map
-                    .get(tip_id)
-                    .unwrap_or_else(|| panic!("expected tip to exist for caller"));
-                Some(tip)
-            } else {
-                None
+
+        fn ensure_role(&self, role: Role) -> Result<(), TipperError> {
+            if !self.has_role(role, self.env().caller()) {
+                return Err(TipperError::MissingRole(role));
             }
+            Ok(())
         }
-        fn get_by_id(&self, id: u32) -> Option<Tip> {
-            self.tip_map.get(id)
+
+        fn ensure_role_manager(&self, role: Role) -> Result<(), TipperError> {
+            match role {
+                Role::Admin => self.ensure_owner(),
+                _ => self.ensure_role(Role::Admin),
+            }
         }
-        fn get_pizza_cost(&self) -> u128 {
-            self.price_per_pizza
+
+        #[ink(message)]
+        pub fn is_paused(&self, scope: PauseScope) -> bool {
+            self.paused.get_or_default().contains(&scope)
         }
+
         #[ink(message)]
-        pub fn terminate_contract(&mut self) {
-            if self.elements_count == 0 {
-                self.env().terminate_contract(self.env().caller());
+        pub fn pause(&mut self, scope: PauseScope) -> Result<(), TipperError> {
+            self.ensure_role(Role::Admin)?;
+            if !self.is_paused(scope) {
+                let mut paused = self.paused.get_or_default();
+                paused.push(scope);
+                self.paused.set(&paused);
+                Self::emit_event(
+                    Self::env(),
+                    Event::Paused(Paused {
+                        scope,
+                        by: self.env().caller(),
+                    }),
+                );
             }
+            Ok(())
         }
-        fn highlight_tip(&self, from: AccountId, id: u32, cost: u128) -> Result<(), TipperError> {
-            if let Some(highlight_pizzas) = self.highlighted_pizzas {
-                let call_result: Result<Result<(), HighlightedPizzasError>, ink::LangError> =
-                    build_call::<DefaultEnvironment>()
-                        .call(highlight_pizzas)
-                        .exec_input(
-                            ExecutionInput::new(Selector::new(HIGHLIGHT_PIZZA_SELECTOR))
-                                .push_arg(from)
-                                .push_arg(id),
-                        )
-                        .transferred_value(cost)
-                        .returns::<Result<Result<(), HighlightedPizzasError>, LangError>>()
-                        .invoke();
-                match call_result {
-                    Err(lang_error) => {
-                        panic!("Unexpected ink::LangError: {:?}", lang_error)
-                    }
-                    Ok(Err(contract_call_error)) => {
-                        return Err(TipperError::HighlightError(contract_call_error))
-                    }
-                    Ok(Ok(_unit)) => return Ok(()),
-                }
+
+        #[ink(message)]
+        pub fn unpause(&mut self, scope: PauseScope) -> Result<(), TipperError> {
+            self.ensure_role(Role::Admin)?;
+            if self.is_paused(scope) {
+                let mut paused = self.paused.get_or_default();
+                paused.retain(|paused| paused != &scope);
+                self.paused.set(&paused);
+                Self::emit_event(
+                    Self::env(),
+                    Event::Unpaused(Unpaused {
+                        scope,
+                        by: self.env().caller(),
+                    }),
+                );
             }
             Ok(())
         }
 
-        fn delete_tip_highlight(&self, from: AccountId) -> Result<(), TipperError> {
-            if let Some(highlight_tip) = self.highlighted_pizzas {
-                <HighlightedPizzasRef as FromAccountId<super::tipper::Environment, >>::from_account_id(highlight_tip).delete_tip_by_author(from);
+        fn ensure_not_paused(&self, scope: PauseScope) -> Result<(), TipperError> {
+            if self.is_paused(scope) {
+                return Err(TipperError::Paused);
             }
             Ok(())
         }
 
-        fn emit_event<EE>(emitter: EE, event: Event)
-        where
-            EE: EmitEvent<Tipper>,
-        {
-            emitter.emit_event(event);
+        #[ink(message)]
+        pub fn version(&self) -> u8 {
+            self.version.get_or_default()
         }
-    }
-
-    #[ink(event)]
-    pub struct PizzaSent {
-        #[ink(topic)]
-        from: AccountId,
-        to: AccountId,
-        id: u32,
-        pizzas: u32,
-    }
-
-    #[ink(event)]
-    pub struct ContentPosted {
-        #[ink(topic)]
-        author: AccountId,
-        id: u32,
-    }
 
-    #[cfg(test)]
-    mod tests {
-        use super::*;
-        use ink::env::{
-            test::{
-                default_accounts, get_account_balance, recorded_events,
-                DefaultAccounts, EmittedEvent,
-            },
-        };
-        use ink::primitives::AccountId;
-        use ink_e2e::subxt::tx::Signer;
-        use ink_e2e::{build_message};
-        use scale::Decode;
-        fn get_test_accts() -> DefaultAccounts<ink::env::DefaultEnvironment> {
-            default_accounts::<ink::env::DefaultEnvironment>()
+        #[ink(message)]
+        pub fn storage_version(&self) -> u8 {
+            self.storage_version.get_or_default()
         }
-        fn get_balance(acct_id: AccountId) -> Balance {
-            get_account_balance::<ink::env::DefaultEnvironment>(acct_id)
-                .expect("can't get account balance")
+
+        /// Replaces the contract code, keeping storage. Call `migrate` afterwards.
+        #[ink(message)]
+        pub fn upgrade(&mut self, code_hash: Hash) -> Result<(), TipperError> {
+            self.ensure_role(Role::Admin)?;
+            self.env()
+                .set_code_hash(&code_hash)
+                .map_err(|_| TipperError::UpgradeFailed)?;
+            Self::emit_event(Self::env(), Event::Upgraded(Upgraded { code_hash }));
+            Ok(())
         }
-        fn set_from(from: AccountId) {
-            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(from);
+
+        /// Rewrites up to `max_tips` tips stored by an older storage version into the current
+        /// layout. Returns `true` once every tip is migrated; tipping is refused until then.
+        #[ink(message)]
+        pub fn migrate(&mut self, max_tips: u32) -> Result<bool, TipperError> {
+            self.ensure_role(Role::Admin)?;
+            let from_version = self.storage_version();
+            if from_version == STORAGE_VERSION {
+                return Ok(true);
+            }
+            let id_counter = self.id_counter.get_or_default();
+            let cursor = self.migration_cursor.get_or_default();
+            let end = cursor.saturating_add(max_tips).min(id_counter);
+            for id in cursor..end {
+                self.migrate_tip(id)?;
+            }
+            self.migration_cursor.set(&end);
+            if end < id_counter {
+                return Ok(false);
+            }
+            self.storage_version.set(&STORAGE_VERSION);
+            self.migration_cursor.set(&0);
+            Self::emit_event(
+                Self::env(),
+                Event::Migrated(Migrated {
+                    from_version,
+                    to_version: STORAGE_VERSION,
+                }),
+            );
+            Ok(true)
         }
 
-        fn tip_from_alice(instance: &mut Tipper) -> Tip {
-            let accts = get_test_accts();
-            let alice = accts.alice;
-            let bob = accts.bob;
-            let msg: ink::prelude::string::String = "dummy".into();
-            let expected_tip = Tip {
-                from: alice,
-                to: bob,
-                pizzas: 1,
-                message: msg.clone(),
+        fn migrate_tip(&mut self, id: u32) -> Result<(), TipperError> {
+            let key = (storage_key_of(&self.tip_map), id);
+            let tip: Option<Tip> = match self.storage_version() {
+                0 | 1 => ink::env::get_contract_storage::<_, TipV0>(&key)
+                    .map_err(|_| TipperError::MigrationFailed(id))?
+                    .map(Tip::from),
+                _ => return Err(TipperError::MigrationFailed(id)),
             };
-            set_from(alice);
-            assert!(instance.tip(msg, bob, 1).is_ok(), "tipping expected");
-            expected_tip
+            if let Some(tip) = tip {
+                self.tip_map.insert(id, &tip);
+            }
+            Ok(())
         }
 
-        const PRICE_PER_PIZZA: u128 = 7;
-
-        #[ink::test]
-        fn constructor_works() {
-            let tipper = Tipper::free();
-            assert_eq!(tipper.price_per_pizza, 0);
+        fn ensure_migrated(&self) -> Result<(), TipperError> {
+            if self.storage_version() != STORAGE_VERSION {
+                return Err(TipperError::MigrationPending);
+            }
+            Ok(())
         }
 
-        #[ink::test]
-        fn pizza_msg_test() {
-            let accts = get_test_accts();
-            let alice = accts.alice;
-            let bob = accts.bob;
-            let mut tipper = Tipper::free();
-            let msg: ink::prelude::string::String = "dummy".into();
-            set_from(alice);
-            let _executed_tip = tipper.tip(msg.clone(), accts.bob, 1);
-            let expected_tip = Tip {
-                from: alice,
-                to: bob,
-                pizzas: 1,
-                message: "dummy".into(),
-            };
-            assert_eq!(tipper.get_by_id(0).unwrap().message, expected_tip.message);
+        #[ink(message)]
+        pub fn set_price_per_pizza(&mut self, price_per_pizza: Balance) -> Result<(), TipperError> {
+            self.ensure_role(Role::PriceSetter)?;
+            self.price_per_pizza.set(&price_per_pizza);
+            Ok(())
         }
 
-        #[ink::test]
-        fn event_on_tip() {
-            let mut instance = Tipper::free();
-            let tip = tip_from_alice(&mut instance);
-            let recorded_events = recorded_events().collect::<Vec<_>>();
-            assert_expected_tip_event(&recorded_events[0], tip.to, tip.from, 0, tip.pizzas);
+        #[ink(message)]
+        pub fn pizza_oracle(&self) -> Option<AccountId> {
+            self.pizza_oracle.get_or_default()
         }
 
-        #[ink::test]
+        #[ink(message)]
+        pub fn set_pizza_oracle(&mut self, pizza_oracle: Option<AccountId>) -> Result<(), TipperError> {
+            self.ensure_role(Role::Admin)?;
+            self.pizza_oracle.set(&pizza_oracle);
+            Ok(())
+        }
+
+        /// Sets which oracle price feed is used and the minimum confidence accepted from it.
+        #[ink(message)]
+        pub fn set_oracle_config(
+            &mut self,
+            oracle_price_id: u32,
+            min_oracle_confidence: u64,
+        ) -> Result<(), TipperError> {
+            self.ensure_role(Role::PriceSetter)?;
+            self.oracle_price_id.set(&oracle_price_id);
+            self.min_oracle_confidence.set(&min_oracle_confidence);
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn oracle_config(&self) -> (u32, u64) {
+            (
+                self.oracle_price_id.get_or_default(),
+                self.min_oracle_confidence.get_or_default(),
+            )
+        }
+
+        /// Sets the maximum length of tip messages and the surcharge paid per message byte,
+        /// which covers the storage deposit of the message.
+        #[ink(message)]
+        pub fn set_message_policy(
+            &mut self,
+            max_message_len: u32,
+            message_byte_fee: Balance,
+        ) -> Result<(), TipperError> {
+            self.ensure_role(Role::Admin)?;
+            self.max_message_len.set(&max_message_len);
+            self.message_byte_fee.set(&message_byte_fee);
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn message_policy(&self) -> (u32, Balance) {
+            (
+                self.max_message_len.get_or_default(),
+                self.message_byte_fee.get_or_default(),
+            )
+        }
+
+        fn validate_message(&self, message: &str) -> Result<(), TipperError> {
+            let max_message_len = self.max_message_len.get_or_default();
+            if message.len() > max_message_len as usize {
+                return Err(TipperError::MessageTooLong(max_message_len));
+            }
+            if message.chars().any(char::is_control) {
+                return Err(TipperError::InvalidMessage);
+            }
+            Ok(())
+        }
+
+        /// Validates a tip message and returns the surcharge for storing it.
+        fn message_surcharge(&self, message: &str) -> Result<Balance, TipperError> {
+            self.validate_message(message)?;
+            self.message_byte_fee
+                .get_or_default()
+                .checked_mul(message.len() as Balance)
+                .ok_or(TipperError::Overflow)
+        }
+
+        /// Price of one pizza as `tip` would charge it right now.
+        #[ink(message)]
+        pub fn current_pizza_price(&self) -> Result<Balance, TipperError> {
+            match self.pizza_oracle() {
+                Some(oracle) => self.price_from_oracle(self.query_oracle(oracle)),
+                None => Ok(self.price_per_pizza()),
+            }
+        }
+
+        fn query_oracle(&self, oracle: AccountId) -> Result<PizzaPrice, TipperError> {
+            let call_result = build_call::<DefaultEnvironment>()
+                .call(oracle)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(GET_PIZZA_PRICE_SELECTOR))
+                        .push_arg(self.oracle_price_id.get_or_default()),
+                )
+                .returns::<Option<PizzaPrice>>()
+                .try_invoke();
+            match call_result {
+                Ok(Ok(Some(price))) => Ok(price),
+                Ok(Ok(None)) => Err(TipperError::OraclePriceNotFound),
+                Ok(Err(_)) | Err(_) => Err(TipperError::OracleUnavailable),
+            }
+        }
+
+        /// Accepts a confident oracle price, rejects a low-confidence one and falls back to
+        /// `price_per_pizza` when the oracle could not answer.
+        fn price_from_oracle(
+            &self,
+            oracle_price: Result<PizzaPrice, TipperError>,
+        ) -> Result<Balance, TipperError> {
+            match oracle_price {
+                Ok(price) if price.confidence < self.min_oracle_confidence.get_or_default() => {
+                    Err(TipperError::OracleConfidenceTooLow(price.confidence))
+                }
+                Ok(price) => Ok(price.current_pizza_price),
+                Err(reason) => {
+                    Self::emit_event(
+                        Self::env(),
+                        Event::OracleFallback(OracleFallback {
+                            reason,
+                            price: self.price_per_pizza(),
+                        }),
+                    );
+                    Ok(self.price_per_pizza())
+                }
+            }
+        }
+
+        #[ink(message)]
+        pub fn azero_router(&self) -> Option<AccountId> {
+            self.azero_router.get_or_default()
+        }
+
+        #[ink(message)]
+        pub fn set_azero_router(&mut self, azero_router: Option<AccountId>) -> Result<(), TipperError> {
+            self.ensure_role(Role::Admin)?;
+            self.azero_router.set(&azero_router);
+            Ok(())
+        }
+
+        /// Resolves an AZERO.ID domain such as `"pizza.azero"` to the account it points at.
+        #[ink(message)]
+        pub fn resolve_domain(&self, name: String) -> ResolveResult<AccountId> {
+            let router = self.azero_router().ok_or(TipperError::ResolverUnavailable)?;
+            let call_result = build_call::<DefaultEnvironment>()
+                .call(router)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(AZERO_ID_GET_ADDRESS_SELECTOR)).push_arg(name),
+                )
+                .returns::<Result<AccountId, u8>>()
+                .try_invoke();
+            match call_result {
+                Ok(Ok(Ok(account))) => Ok(account),
+                Ok(Ok(Err(_))) => Err(TipperError::DoesntExist),
+                Ok(Err(_)) | Err(_) => Err(TipperError::ResolverUnavailable),
+            }
+        }
+
+        #[ink(message)]
+        pub fn highlighted_pizzas(&self) -> Option<AccountId> {
+            self.highlighted_pizzas.get_or_default()
+        }
+
+        /// Attaches an existing HighlightedPizzas deployment, or detaches it with `None`. It
+        /// only accepts calls from its `created_by` account, so deployments that weren't
+        /// instantiated by this Tipper are rejected.
+        #[ink(message)]
+        pub fn set_highlighted_pizzas(
+            &mut self,
+            highlighted_pizzas: Option<AccountId>,
+        ) -> Result<(), TipperError> {
+            self.ensure_role(Role::Admin)?;
+            if let Some(account) = highlighted_pizzas {
+                self.ensure_highlights_created_by_self(account)?;
+            }
+            self.highlighted_pizzas.set(&highlighted_pizzas);
+            Ok(())
+        }
+
+        fn ensure_highlights_created_by_self(&self, account: AccountId) -> Result<(), TipperError> {
+            let highlighted: contract_ref!(HighlightPizzas) = account.into();
+            match highlighted.call().created_by().try_invoke() {
+                Ok(Ok(created_by)) if created_by == self.env().account_id() => Ok(()),
+                Ok(Ok(_)) => Err(TipperError::HighlightError(
+                    HighlightedPizzasError::AccessDenied,
+                )),
+                Ok(Err(_)) | Err(_) => Err(TipperError::HighlightUnavailable),
+            }
+        }
+
+        #[ink(message)]
+        pub fn get_pizza_tippers(&self) -> Vec<AccountId> {
+            self.pizza_tippers.get_or_default()
+        }
+
+        /// Total pizzas `tipper` has sent to anyone.
+        #[ink(message)]
+        pub fn pizzas_sent_by(&self, tipper: AccountId) -> u64 {
+            self.pizzas_sent.get(tipper).unwrap_or(0)
+        }
+
+        /// Total pizzas `tipper` has sent to `creator`.
+        #[ink(message)]
+        pub fn pizzas_sent_to(&self, tipper: AccountId, creator: AccountId) -> u64 {
+            self.pizzas_to_creator.get((tipper, creator)).unwrap_or(0)
+        }
+
+        /// Returns up to `LEADERBOARD_SIZE` tippers of `creator` with their pizza totals,
+        /// highest first.
+        #[ink(message)]
+        pub fn top_tippers_of(&self, creator: AccountId) -> Leaderboard {
+            self.creator_leaderboards.get(creator).unwrap_or_default()
+        }
+
+        /// Returns up to `LEADERBOARD_SIZE` tippers with their total pizzas sent, highest
+        /// first.
+        #[ink(message)]
+        pub fn top_tippers_global(&self) -> Leaderboard {
+            self.global_leaderboard.get_or_default()
+        }
+
+        /// Adds a tip of `pizzas` to the running totals and leaderboards.
+        fn record_pizzas(&mut self, from: AccountId, to: AccountId, pizzas: u32) {
+            if pizzas == 0 {
+                return;
+            }
+            let sent = self.pizzas_sent_by(from).saturating_add(pizzas.into());
+            self.pizzas_sent.insert(from, &sent);
+            let mut global_leaderboard = self.top_tippers_global();
+            rank(&mut global_leaderboard, from, sent);
+            self.global_leaderboard.set(&global_leaderboard);
+
+            let sent_to = self.pizzas_sent_to(from, to).saturating_add(pizzas.into());
+            self.pizzas_to_creator.insert((from, to), &sent_to);
+            let mut leaderboard = self.top_tippers_of(to);
+            if rank(&mut leaderboard, from, sent_to) {
+                self.creator_leaderboards.insert(to, &leaderboard);
+            }
+        }
+
+
+        /// Tips `to` with `n_pizzas` pizzas plus an optional `extra_gift` on top, optionally
+        /// for one of their posts.
+        ///
+        /// Exactly the pizza cost plus `extra_gift` is charged; anything transferred beyond
+        /// that is refunded to the caller.
+        #[ink(message, payable)]
+        pub fn tip(
+            &mut self,
+            tip_message: String,
+            to: AccountId,
+            n_pizzas: u32,
+            extra_gift: Balance,
+            content_id: Option<u32>,
+        ) -> Result<(), TipperError> {
+            self.ensure_not_paused(PauseScope::Tipping)?;
+            self.ensure_migrated()?;
+            let from = Self::env().caller();
+            ink::env::debug_println!(
+                "{:?} wants to tip {:?} with {:?} pizzas with the message '{:?}' ",
+                from,
+                to,
+                n_pizzas,
+                tip_message
+            );
+            // if self.id_map.contains(from) {
+            //     return Err(Error::AlreadyTipped);
+            // }
+            if let Some(content_id) = content_id {
+                self.ensure_post_of(content_id, to)?;
+            }
+            let surcharge = self.message_surcharge(&tip_message)?;
+            let transfered_amount = self.env().transferred_value();
+            let pizza_cost = self
+                .current_pizza_price()?
+                .checked_mul(n_pizzas.into())
+                .and_then(|cost| cost.checked_add(extra_gift))
+                .ok_or(TipperError::Overflow)?;
+            let total_cost = pizza_cost
+                .checked_add(surcharge)
+                .ok_or(TipperError::Overflow)?;
+
+            if transfered_amount < total_cost {
+                return Err(TipperError::InsufficientAmount(total_cost));
+            }
+            let (amount, fee) = self.split_fee(pizza_cost);
+            self.credit_earnings(self.payout_account_of(to), amount)?;
+            self.credit_treasury(fee)?;
+            self.credit_treasury(surcharge)?;
+            let tip = Tip {
+                from,
+                to,
+                pizzas: n_pizzas,
+                message: tip_message,
+                content_id,
+            };
+            let event = self._tip(tip, amount, fee)?;
+            Self::emit_event(Self::env(), Event::PizzaSent(event));
+            self.refund_change(from, transfered_amount - total_cost);
+            Ok(())
+        }
+
+        /// Tips the owner of the AZERO.ID domain `name`, charging like `tip`.
+        #[ink(message, payable)]
+        pub fn tip_domain(
+            &mut self,
+            name: String,
+            n_pizzas: u32,
+            tip_message: String,
+        ) -> Result<(), TipperError> {
+            let to = self.resolve_domain(name)?;
+            self.tip(tip_message, to, n_pizzas, 0, None)
+        }
+
+        /// Sends one tip per `(to, n_pizzas, message)` entry, charging like `tip`. The total
+        /// cost is checked before any tip is recorded, so either all entries are applied or
+        /// none. Returns the new tip ids, in entry order.
+        #[ink(message, payable)]
+        pub fn tip_batch(
+            &mut self,
+            entries: Vec<(AccountId, u32, String)>,
+        ) -> Result<Vec<u32>, TipperError> {
+            self.ensure_not_paused(PauseScope::Tipping)?;
+            self.ensure_migrated()?;
+            if entries.is_empty() {
+                return Err(TipperError::EmptyBatch);
+            }
+            let max_batch_size = self.max_batch_size();
+            if entries.len() > max_batch_size as usize {
+                return Err(TipperError::BatchTooLarge(max_batch_size));
+            }
+            let from = Self::env().caller();
+            let transfered_amount = self.env().transferred_value();
+            let price = self.current_pizza_price()?;
+            let mut surcharge: Balance = 0;
+            for (_, _, tip_message) in &entries {
+                surcharge = surcharge
+                    .checked_add(self.message_surcharge(tip_message)?)
+                    .ok_or(TipperError::Overflow)?;
+            }
+            let costs = entries
+                .iter()
+                .map(|(_, n_pizzas, _)| price.checked_mul((*n_pizzas).into()))
+                .collect::<Option<Vec<Balance>>>()
+                .ok_or(TipperError::Overflow)?;
+            let total_cost = costs
+                .iter()
+                .try_fold(surcharge, |total, cost| total.checked_add(*cost))
+                .ok_or(TipperError::Overflow)?;
+            if transfered_amount < total_cost {
+                return Err(TipperError::InsufficientAmount(total_cost));
+            }
+
+            self.credit_treasury(surcharge)?;
+            let mut ids = Vec::with_capacity(entries.len());
+            for ((to, n_pizzas, tip_message), cost) in entries.into_iter().zip(costs) {
+                let (amount, fee) = self.split_fee(cost);
+                self.credit_earnings(self.payout_account_of(to), amount)?;
+                self.credit_treasury(fee)?;
+                let tip = Tip {
+                    from,
+                    to,
+                    pizzas: n_pizzas,
+                    message: tip_message,
+                    content_id: None,
+                };
+                let event = self._tip(tip, amount, fee)?;
+                ids.push(event.id);
+                Self::emit_event(Self::env(), Event::PizzaSent(event));
+            }
+            self.refund_change(from, transfered_amount - total_cost);
+            Ok(ids)
+        }
+
+        #[ink(message)]
+        pub fn max_batch_size(&self) -> u32 {
+            self.max_batch_size.get_or_default()
+        }
+
+        #[ink(message)]
+        pub fn set_max_batch_size(&mut self, max_batch_size: u32) -> Result<(), TipperError> {
+            self.ensure_role(Role::Admin)?;
+            self.max_batch_size.set(&max_batch_size);
+            Ok(())
+        }
+
+        /// Tips several creators at once: the cost of `n_pizzas` is divided between
+        /// `recipients` in proportion to their weights, and one tip is recorded per
+        /// recipient under a shared group id, which is returned.
+        ///
+        /// Both pizzas and balance are apportioned by largest remainder: every recipient
+        /// gets the rounded-down share, and the units left over go one each to the
+        /// recipients whose exact shares were rounded down the most, earlier recipients
+        /// first on ties.
+        #[ink(message, payable)]
+        pub fn tip_split(
+            &mut self,
+            tip_message: String,
+            recipients: Vec<(AccountId, u16)>,
+            n_pizzas: u32,
+        ) -> Result<u32, TipperError> {
+            self.ensure_not_paused(PauseScope::Tipping)?;
+            self.ensure_migrated()?;
+            let weights = split_weights(&recipients)?;
+            // the message is stored once per recipient
+            let surcharge = self
+                .message_surcharge(&tip_message)?
+                .checked_mul(recipients.len() as Balance)
+                .ok_or(TipperError::Overflow)?;
+            let from = Self::env().caller();
+            let transfered_amount = self.env().transferred_value();
+            let pizza_cost = self
+                .current_pizza_price()?
+                .checked_mul(n_pizzas.into())
+                .ok_or(TipperError::Overflow)?;
+            let total_cost = pizza_cost
+                .checked_add(surcharge)
+                .ok_or(TipperError::Overflow)?;
+            if transfered_amount < total_cost {
+                return Err(TipperError::InsufficientAmount(total_cost));
+            }
+            let (amount, fee) = self.split_fee(pizza_cost);
+            self.credit_treasury(fee)?;
+            self.credit_treasury(surcharge)?;
+
+            let group_id = next_id(&mut self.split_counter)?;
+            let first_id = self.id_counter.get_or_default();
+            let shares = split_by_weight(amount, &weights);
+            let pizza_shares = split_by_weight(n_pizzas.into(), &weights);
+            for (((to, _), share), pizzas) in recipients.iter().zip(shares).zip(pizza_shares) {
+                self.credit_earnings(self.payout_account_of(*to), share)?;
+                let tip = Tip {
+                    from,
+                    to: *to,
+                    // never exceeds n_pizzas
+                    pizzas: pizzas as u32,
+                    message: tip_message.clone(),
+                    content_id: None,
+                };
+                let tip_id = self.insert_tip(&from, tip);
+                self.tip_groups.insert(tip_id, &group_id);
+            }
+            self.split_ranges
+                .insert(group_id, &(first_id, recipients.len() as u32));
+
+            Self::emit_event(
+                Self::env(),
+                Event::PizzaSplitSent(PizzaSplitSent {
+                    from,
+                    group_id,
+                    recipients: recipients.into_iter().map(|(to, _)| to).collect(),
+                    first_id,
+                    pizzas: n_pizzas,
+                    amount,
+                    fee,
+                }),
+            );
+            self.refund_change(from, transfered_amount - total_cost);
+            Ok(group_id)
+        }
+
+        /// Returns the split group tip `id` belongs to, if it was sent with `tip_split`.
+        #[ink(message)]
+        pub fn split_group_of(&self, id: u32) -> Option<u32> {
+            self.tip_groups.get(id)
+        }
+
+        /// Returns the tips recorded by the split `group_id`, in recipient order.
+        #[ink(message)]
+        pub fn split_tips(&self, group_id: u32) -> Vec<TipView> {
+            let Some((first_id, count)) = self.split_ranges.get(group_id) else {
+                return Vec::new();
+            };
+            (first_id..first_id + count)
+                .filter_map(|id| self.get_tip(id))
+                .collect()
+        }
+
+        /// Subscribes the caller to `creator` for `periods` periods of `pizzas_per_period`
+        /// pizzas each, prepaying all of them at the current pizza price. Returns the
+        /// subscription id.
+        #[ink(message, payable)]
+        pub fn subscribe(
+            &mut self,
+            creator: AccountId,
+            pizzas_per_period: u32,
+            period_ms: u64,
+            periods: u32,
+        ) -> Result<u32, TipperError> {
+            self.ensure_not_paused(PauseScope::Tipping)?;
+            self.ensure_migrated()?;
+            if pizzas_per_period == 0 || period_ms == 0 || periods == 0 {
+                return Err(TipperError::InvalidSubscription);
+            }
+            let subscriber = self.env().caller();
+            let transfered_amount = self.env().transferred_value();
+            let price_per_period = self
+                .current_pizza_price()?
+                .checked_mul(pizzas_per_period.into())
+                .ok_or(TipperError::Overflow)?;
+            let total = price_per_period
+                .checked_mul(periods.into())
+                .ok_or(TipperError::Overflow)?;
+            if transfered_amount < total {
+                return Err(TipperError::InsufficientAmount(total));
+            }
+            let escrow = self
+                .subscription_escrow
+                .get_or_default()
+                .checked_add(total)
+                .ok_or(TipperError::Overflow)?;
+            self.subscription_escrow.set(&escrow);
+
+            let id = next_id(&mut self.subscription_counter)?;
+            let subscription = Subscription {
+                id,
+                subscriber,
+                creator,
+                pizzas_per_period,
+                period_ms,
+                periods,
+                price_per_period,
+                started_at: self.env().block_timestamp(),
+                claimed_periods: 0,
+                cancelled: false,
+            };
+            self.subscriptions.insert(id, &subscription);
+            let nth = self.subscription_count_of(subscriber);
+            self.subscriber_subs.insert((subscriber, nth), &id);
+            self.subscriber_sub_counts.insert(subscriber, &(nth + 1));
+            let nth = self.subscriber_count_of(creator);
+            self.creator_subs.insert((creator, nth), &id);
+            self.creator_sub_counts.insert(creator, &(nth + 1));
+
+            Self::emit_event(
+                Self::env(),
+                Event::Subscribed(Subscribed {
+                    id,
+                    subscriber,
+                    creator,
+                    periods,
+                    amount: total,
+                }),
+            );
+            self.refund_change(subscriber, transfered_amount - total);
+            Ok(id)
+        }
+
+        /// Pays the periods of subscription `id` accrued so far to its creator, who must be
+        /// the caller. Returns the number of periods claimed.
+        #[ink(message)]
+        pub fn claim_subscription(&mut self, id: u32) -> Result<u32, TipperError> {
+            self.ensure_migrated()?;
+            let mut subscription = self
+                .get_subscription(id)
+                .ok_or(TipperError::SubscriptionNotFound)?;
+            if subscription.creator != self.env().caller() {
+                return Err(TipperError::NotSubscribedCreator);
+            }
+            if subscription.cancelled {
+                return Err(TipperError::SubscriptionCancelled);
+            }
+            let periods = self.settle_subscription(&mut subscription)?;
+            if periods == 0 {
+                return Err(TipperError::NothingToClaim);
+            }
+            self.subscriptions.insert(id, &subscription);
+            Ok(periods)
+        }
+
+        /// Cancels subscription `id` of the caller. Periods accrued so far are paid to the
+        /// creator and the prepaid remainder is refunded. Returns the refunded amount.
+        #[ink(message)]
+        pub fn cancel_subscription(&mut self, id: u32) -> Result<Balance, TipperError> {
+            self.ensure_migrated()?;
+            self.ensure_not_paused(PauseScope::Withdrawals)?;
+            let mut subscription = self
+                .get_subscription(id)
+                .ok_or(TipperError::SubscriptionNotFound)?;
+            if subscription.subscriber != self.env().caller() {
+                return Err(TipperError::NotSubscriber);
+            }
+            if subscription.cancelled {
+                return Err(TipperError::SubscriptionCancelled);
+            }
+            self.settle_subscription(&mut subscription)?;
+            let refund = subscription.price_per_period
+                * Balance::from(subscription.periods - subscription.claimed_periods);
+            subscription.periods = subscription.claimed_periods;
+            subscription.cancelled = true;
+            self.subscriptions.insert(id, &subscription);
+            self.subscription_escrow
+                .set(&(self.subscription_escrow.get_or_default() - refund));
+            if refund > 0 {
+                self.env()
+                    .transfer(subscription.subscriber, refund)
+                    .map_err(|_| TipperError::TransferFailed)?;
+            }
+            Self::emit_event(
+                Self::env(),
+                Event::SubscriptionCancelled(SubscriptionCancelled {
+                    id,
+                    subscriber: subscription.subscriber,
+                    refund,
+                }),
+            );
+            Ok(refund)
+        }
+
+        /// Credits the creator with the periods accrued since the last claim and records
+        /// them as a tip from the subscriber. Returns the number of periods paid.
+        fn settle_subscription(
+            &mut self,
+            subscription: &mut Subscription,
+        ) -> Result<u32, TipperError> {
+            let periods = self.claimable_periods_of(subscription);
+            if periods == 0 {
+                return Ok(0);
+            }
+            let payment = subscription.price_per_period * Balance::from(periods);
+            let pizzas = subscription
+                .pizzas_per_period
+                .checked_mul(periods)
+                .ok_or(TipperError::Overflow)?;
+            let (amount, fee) = self.split_fee(payment);
+            self.subscription_escrow
+                .set(&(self.subscription_escrow.get_or_default() - payment));
+            self.credit_earnings(self.payout_account_of(subscription.creator), amount)?;
+            self.credit_treasury(fee)?;
+            subscription.claimed_periods += periods;
+            let tip = Tip {
+                from: subscription.subscriber,
+                to: subscription.creator,
+                pizzas,
+                message: String::new(),
+                content_id: None,
+            };
+            let event = self._tip(tip, amount, fee)?;
+            Self::emit_event(Self::env(), Event::PizzaSent(event));
+            Self::emit_event(
+                Self::env(),
+                Event::SubscriptionClaimed(SubscriptionClaimed {
+                    id: subscription.id,
+                    creator: subscription.creator,
+                    periods,
+                    amount,
+                }),
+            );
+            Ok(periods)
+        }
+
+        fn claimable_periods_of(&self, subscription: &Subscription) -> u32 {
+            if subscription.cancelled {
+                return 0;
+            }
+            let elapsed = self
+                .env()
+                .block_timestamp()
+                .saturating_sub(subscription.started_at);
+            let accrued = (elapsed / subscription.period_ms).min(subscription.periods.into());
+            // bounded by `periods` above
+            accrued as u32 - subscription.claimed_periods
+        }
+
+        #[ink(message)]
+        pub fn get_subscription(&self, id: u32) -> Option<Subscription> {
+            self.subscriptions.get(id)
+        }
+
+        /// Periods of subscription `id` that have accrued but were not claimed yet.
+        #[ink(message)]
+        pub fn claimable_periods(&self, id: u32) -> u32 {
+            self.get_subscription(id)
+                .map_or(0, |subscription| self.claimable_periods_of(&subscription))
+        }
+
+        #[ink(message)]
+        pub fn subscription_count_of(&self, subscriber: AccountId) -> u32 {
+            self.subscriber_sub_counts.get(subscriber).unwrap_or(0)
+        }
+
+        /// Returns the subscriptions taken out by `subscriber`, newest first.
+        #[ink(message)]
+        pub fn subscriptions_of(
+            &self,
+            subscriber: AccountId,
+            offset: u32,
+            limit: u32,
+        ) -> Vec<Subscription> {
+            let count = self.subscription_count_of(subscriber);
+            page(count, offset, limit)
+                .filter_map(|i| self.subscriber_subs.get((subscriber, count - 1 - i)))
+                .filter_map(|id| self.get_subscription(id))
+                .collect()
+        }
+
+        #[ink(message)]
+        pub fn subscriber_count_of(&self, creator: AccountId) -> u32 {
+            self.creator_sub_counts.get(creator).unwrap_or(0)
+        }
+
+        /// Returns the subscriptions to `creator`, newest first.
+        #[ink(message)]
+        pub fn subscribers_of(
+            &self,
+            creator: AccountId,
+            offset: u32,
+            limit: u32,
+        ) -> Vec<Subscription> {
+            let count = self.subscriber_count_of(creator);
+            page(count, offset, limit)
+                .filter_map(|i| self.creator_subs.get((creator, count - 1 - i)))
+                .filter_map(|id| self.get_subscription(id))
+                .collect()
+        }
+
+        /// Opens a payment channel to `creator` funded with the transferred value. Vouchers
+        /// must be signed with the ECDSA key of account `signer` and can be redeemed until
+        /// `expires_at`. Returns the channel id.
+        #[ink(message, payable)]
+        pub fn open_channel(
+            &mut self,
+            creator: AccountId,
+            signer: AccountId,
+            expires_at: Timestamp,
+        ) -> Result<u32, TipperError> {
+            self.ensure_not_paused(PauseScope::Tipping)?;
+            let tipper = self.env().caller();
+            let deposit = self.env().transferred_value();
+            if deposit == 0 || expires_at <= self.env().block_timestamp() {
+                return Err(TipperError::InvalidChannel);
+            }
+            let escrow = self
+                .channel_escrow
+                .get_or_default()
+                .checked_add(deposit)
+                .ok_or(TipperError::Overflow)?;
+            self.channel_escrow.set(&escrow);
+            let id = next_id(&mut self.channel_counter)?;
+            let channel = Channel {
+                id,
+                tipper,
+                creator,
+                signer,
+                deposit,
+                redeemed: 0,
+                expires_at,
+                closing_at: None,
+            };
+            self.channels.insert(id, &channel);
+            Self::emit_event(
+                Self::env(),
+                Event::ChannelOpened(ChannelOpened {
+                    id,
+                    tipper,
+                    creator,
+                    deposit,
+                    expires_at,
+                }),
+            );
+            Ok(id)
+        }
+
+        /// Hash a voucher for `cumulative_amount` on channel `id` is signed over.
+        #[ink(message)]
+        pub fn voucher_hash(&self, id: u32, cumulative_amount: Balance) -> [u8; 32] {
+            self.env()
+                .hash_encoded::<Blake2x256, _>(&(self.env().account_id(), id, cumulative_amount))
+        }
+
+        /// Redeems a voucher of channel `id`, paying the creator the difference between
+        /// `cumulative_amount` and what was already redeemed. Returns the amount paid.
+        #[ink(message)]
+        pub fn redeem_voucher(
+            &mut self,
+            id: u32,
+            cumulative_amount: Balance,
+            signature: [u8; 65],
+        ) -> Result<Balance, TipperError> {
+            let mut channel = self.get_channel(id).ok_or(TipperError::ChannelNotFound)?;
+            if channel.creator != self.env().caller() {
+                return Err(TipperError::NotChannelCreator);
+            }
+            if self.env().block_timestamp() >= redeem_deadline(&channel) {
+                return Err(TipperError::ChannelExpired);
+            }
+            if cumulative_amount <= channel.redeemed {
+                return Err(TipperError::InvalidVoucher);
+            }
+            if cumulative_amount > channel.deposit {
+                return Err(TipperError::VoucherExceedsDeposit);
+            }
+            let hash = self.voucher_hash(id, cumulative_amount);
+            let public_key = self
+                .env()
+                .ecdsa_recover(&signature, &hash)
+                .map_err(|_| TipperError::InvalidVoucher)?;
+            let signer = self.env().hash_bytes::<Blake2x256>(&public_key);
+            if AccountId::from(signer) != channel.signer {
+                return Err(TipperError::InvalidVoucher);
+            }
+
+            let payment = cumulative_amount - channel.redeemed;
+            let (amount, fee) = self.split_fee(payment);
+            self.channel_escrow
+                .set(&(self.channel_escrow.get_or_default() - payment));
+            self.credit_earnings(self.payout_account_of(channel.creator), amount)?;
+            self.credit_treasury(fee)?;
+            channel.redeemed = cumulative_amount;
+            self.channels.insert(id, &channel);
+            Self::emit_event(
+                Self::env(),
+                Event::VoucherRedeemed(VoucherRedeemed {
+                    id,
+                    creator: channel.creator,
+                    cumulative_amount,
+                    amount,
+                    fee,
+                }),
+            );
+            Ok(payment)
+        }
+
+        /// Starts closing channel `id` early. The creator can still redeem vouchers for
+        /// `CHANNEL_CHALLENGE_PERIOD_MS`, after which the tipper can reclaim the rest.
+        #[ink(message)]
+        pub fn start_channel_close(&mut self, id: u32) -> Result<Timestamp, TipperError> {
+            let mut channel = self.own_channel(id)?;
+            let now = self.env().block_timestamp();
+            if now >= redeem_deadline(&channel) {
+                return Err(TipperError::ChannelExpired);
+            }
+            let closing_at = now.saturating_add(CHANNEL_CHALLENGE_PERIOD_MS);
+            channel.closing_at = Some(closing_at);
+            self.channels.insert(id, &channel);
+            Self::emit_event(
+                Self::env(),
+                Event::ChannelClosing(ChannelClosing { id, closing_at }),
+            );
+            Ok(redeem_deadline(&channel))
+        }
+
+        /// Returns the unredeemed deposit of channel `id` to the tipper once the channel
+        /// expired or its challenge window ended, and removes the channel.
+        #[ink(message)]
+        pub fn reclaim_channel(&mut self, id: u32) -> Result<Balance, TipperError> {
+            self.ensure_not_paused(PauseScope::Withdrawals)?;
+            let channel = self.own_channel(id)?;
+            if self.env().block_timestamp() < redeem_deadline(&channel) {
+                return Err(TipperError::ChannelStillOpen);
+            }
+            let refund = channel.deposit - channel.redeemed;
+            self.channels.remove(id);
+            self.channel_escrow
+                .set(&(self.channel_escrow.get_or_default() - refund));
+            if refund > 0 {
+                self.env()
+                    .transfer(channel.tipper, refund)
+                    .map_err(|_| TipperError::TransferFailed)?;
+            }
+            Self::emit_event(
+                Self::env(),
+                Event::ChannelSettled(ChannelSettled { id, refund }),
+            );
+            Ok(refund)
+        }
+
+        #[ink(message)]
+        pub fn get_channel(&self, id: u32) -> Option<Channel> {
+            self.channels.get(id)
+        }
+
+        fn own_channel(&self, id: u32) -> Result<Channel, TipperError> {
+            let channel = self.get_channel(id).ok_or(TipperError::ChannelNotFound)?;
+            if channel.tipper != self.env().caller() {
+                return Err(TipperError::NotChannelTipper);
+            }
+            Ok(channel)
+        }
+
+        fn _tip(&mut self, tip: Tip, amount: Balance, fee: Balance) -> Result<PizzaSent, TipperError> {
+            let (from, to, pizzas, content_id) = (tip.from, tip.to, tip.pizzas, tip.content_id);
+            let tip_id = self.insert_tip(&from, tip);
+            if let Some(content_id) = content_id {
+                self.record_post_tip(content_id, tip_id, pizzas)?;
+            }
+
+            Ok(PizzaSent {
+                from,
+                to,
+                pizzas,
+                id: tip_id,
+                amount,
+                fee,
+                content_id,
+            })
+        }
+
+        fn insert_tip(&mut self, from: &AccountId, tip: Tip) -> u32 {
+            let pizza_id = self.id_counter.get_or_default();
+            let nth = self.tip_count_of(*from);
+            self.id_map.insert((from, nth), &pizza_id);
+            self.tip_counts.insert(from, &(nth + 1));
+            let received = self.received_count_of(tip.to);
+            self.received_map.insert((tip.to, received), &pizza_id);
+            self.received_counts.insert(tip.to, &(received + 1));
+            self.record_pizzas(*from, tip.to, tip.pizzas);
+            self.tip_map.insert(pizza_id, &tip);
+            self.id_counter.set(&(pizza_id + 1));
+            if nth == 0 {
+                let mut pizza_tippers = self.get_pizza_tippers();
+                pizza_tippers.push(*from);
+                self.pizza_tippers.set(&pizza_tippers);
+            }
+            pizza_id
+        }
+        /// Splits `payment` into the recipient's share and the protocol fee.
+        fn split_fee(&self, payment: Balance) -> (Balance, Balance) {
+            let bps = Balance::from(self.fee_bps());
+            let denominator = Balance::from(BPS_DENOMINATOR);
+            // fee_bps <= BPS_DENOMINATOR, so neither term can overflow and fee <= payment
+            let fee = payment / denominator * bps + payment % denominator * bps / denominator;
+            (payment - fee, fee)
+        }
+
+        fn credit_treasury(&mut self, fee: Balance) -> Result<(), TipperError> {
+            let treasury_balance = self
+                .treasury_balance()
+                .checked_add(fee)
+                .ok_or(TipperError::Overflow)?;
+            self.treasury_balance.set(&treasury_balance);
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn treasury_balance(&self) -> Balance {
+            self.treasury_balance.get_or_default()
+        }
+
+        #[ink(message)]
+        pub fn fee_bps(&self) -> u16 {
+            self.fee_bps.get_or_default()
+        }
+
+        #[ink(message)]
+        pub fn treasury(&self) -> AccountId {
+            // set by every constructor
+            self.treasury.get().expect("treasury is set on instantiation")
+        }
+
+        #[ink(message)]
+        pub fn set_fee_bps(&mut self, fee_bps: u16) -> Result<(), TipperError> {
+            self.ensure_role(Role::Admin)?;
+            if fee_bps > BPS_DENOMINATOR {
+                return Err(TipperError::InvalidFee);
+            }
+            self.fee_bps.set(&fee_bps);
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn set_treasury(&mut self, treasury: AccountId) -> Result<(), TipperError> {
+            self.ensure_role(Role::Admin)?;
+            self.treasury.set(&treasury);
+            Ok(())
+        }
+
+        /// Sends the collected fees to the treasury account.
+        #[ink(message)]
+        pub fn withdraw_fees(&mut self) -> Result<Balance, TipperError> {
+            self.ensure_not_paused(PauseScope::Withdrawals)?;
+            let treasury = self.treasury();
+            if self.env().caller() != treasury {
+                self.ensure_role(Role::Admin)?;
+            }
+            let amount = self.treasury_balance();
+            if amount == 0 {
+                return Err(TipperError::NoEarnings);
+            }
+            self.treasury_balance.set(&0);
+            self.env()
+                .transfer(treasury, amount)
+                .map_err(|_| TipperError::TransferFailed)?;
+            Self::emit_event(
+                Self::env(),
+                Event::EarningsWithdrawn(EarningsWithdrawn {
+                    account: treasury,
+                    amount,
+                }),
+            );
+            Ok(amount)
+        }
+
+        /// Credits `amount` to the earnings ledger of `to`, to be claimed with `withdraw`.
+        fn credit_earnings(&mut self, to: AccountId, amount: Balance) -> Result<(), TipperError> {
+            let earned = self
+                .earnings_of(to)
+                .checked_add(amount)
+                .ok_or(TipperError::Overflow)?;
+            let outstanding = self
+                .outstanding_earnings
+                .get_or_default()
+                .checked_add(amount)
+                .ok_or(TipperError::Overflow)?;
+            self.outstanding_earnings.set(&outstanding);
+            self.earnings.insert(to, &earned);
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn earnings_of(&self, account: AccountId) -> Balance {
+            self.earnings.get(account).unwrap_or(0)
+        }
+
+        /// Withdraws `amount` of the caller's earnings to the caller.
+        #[ink(message)]
+        pub fn withdraw(&mut self, amount: Balance) -> Result<(), TipperError> {
+            self.ensure_not_paused(PauseScope::Withdrawals)?;
+            let caller = self.env().caller();
+            let earned = self.earnings_of(caller);
+            if amount > earned {
+                return Err(TipperError::InsufficientEarnings(earned));
+            }
+            self.payout(caller, earned - amount, amount)
+        }
+
+        /// Withdraws all of the caller's earnings and returns the amount paid out.
+        #[ink(message)]
+        pub fn withdraw_all(&mut self) -> Result<Balance, TipperError> {
+            self.ensure_not_paused(PauseScope::Withdrawals)?;
+            let caller = self.env().caller();
+            let earned = self.earnings_of(caller);
+            if earned == 0 {
+                return Err(TipperError::NoEarnings);
+            }
+            self.payout(caller, 0, earned)?;
+            Ok(earned)
+        }
+
+        fn payout(
+            &mut self,
+            account: AccountId,
+            remaining: Balance,
+            amount: Balance,
+        ) -> Result<(), TipperError> {
+            if remaining == 0 {
+                self.earnings.remove(account);
+            } else {
+                self.earnings.insert(account, &remaining);
+            }
+            self.outstanding_earnings
+                .set(&(self.outstanding_earnings.get_or_default() - amount));
+            self.env()
+                .transfer(account, amount)
+                .map_err(|_| TipperError::TransferFailed)?;
+            Self::emit_event(
+                Self::env(),
+                Event::EarningsWithdrawn(EarningsWithdrawn { account, amount }),
+            );
+            Ok(())
+        }
+
+        fn refund_change(&self, to: AccountId, amount: Balance) {
+            if amount == 0 {
+                return;
+            }
+            self.reimburse(to, amount);
+            Self::emit_event(
+                Self::env(),
+                Event::ChangeRefunded(ChangeRefunded { to, amount }),
+            );
+        }
+
+        fn reimburse(&self, to: AccountId, amount: u128) {
+            if Self::env().transfer(to, amount).is_err() {
+                panic!("failed to reimburse caller")
+            }
+        }
+        #[ink(message)]
+        pub fn tip_count_of(&self, from: AccountId) -> u32 {
+            self.tip_counts.get(from).unwrap_or(0)
+        }
+
+        /// Returns the ids of tips sent by `from`, oldest first, at most `MAX_PAGE_SIZE` per page.
+        #[ink(message)]
+        pub fn tips_by_tipper(&self, from: AccountId, offset: u32, limit: u32) -> Vec<u32> {
+            page(self.tip_count_of(from), offset, limit)
+                .filter_map(|nth| self.id_map.get((from, nth)))
+                .collect()
+        }
+
+        #[ink(message)]
+        pub fn received_count_of(&self, to: AccountId) -> u32 {
+            self.received_counts.get(to).unwrap_or(0)
+        }
+
+        /// Returns the tips received by `to`, newest first.
+        #[ink(message)]
+        pub fn tips_for_creator(&self, to: AccountId, offset: u32, limit: u32) -> Vec<TipView> {
+            let count = self.received_count_of(to);
+            page(count, offset, limit)
+                .filter_map(|i| self.received_map.get((to, count - 1 - i)))
+                .filter_map(|id| self.get_tip(id))
+                .filter(TipView::is_listed)
+                .collect()
+        }
+
+        #[ink(message)]
+        pub fn get_tip(&self, id: u32) -> Option<TipView> {
+            self.get_by_id(id).map(|tip| {
+                TipView::new(id, tip, self.visibility_of(id), self.tip_replies.get(id))
+            })
+        }
+
+        /// Returns the most recent tip sent by `account`.
+        #[ink(message)]
+        pub fn latest_tip_of(&self, account: AccountId) -> Option<TipView> {
+            let latest = self.tip_count_of(account).checked_sub(1)?;
+            let tip_id = self.id_map.get((account, latest))?;
+            self.get_tip(tip_id)
+        }
+
+        #[ink(message)]
+        pub fn price_per_pizza(&self) -> Balance {
+            self.price_per_pizza.get_or_default()
+        }
+
+        fn get_by_id(&self, id: u32) -> Option<Tip> {
+            self.tip_map.get(id)
+        }
+        #[ink(message)]
+        pub fn terminate_contract(&mut self) -> Result<(), TipperError> {
+            self.ensure_owner()?;
+            if self.elements_count.get_or_default() == 0
+                && self.outstanding_earnings.get_or_default() == 0
+                && self.treasury_balance() == 0
+                && self.subscription_escrow.get_or_default() == 0
+                && self.channel_escrow.get_or_default() == 0
+            {
+                self.env().terminate_contract(self.env().caller());
+            }
+            Ok(())
+        }
+        /// Publishes a post by the caller and returns its id.
+        #[ink(message)]
+        pub fn post_content(
+            &mut self,
+            uri: String,
+            content_hash: Hash,
+            title: String,
+        ) -> Result<u32, TipperError> {
+            validate_post(&uri, &title)?;
+            let author = self.env().caller();
+            let id = next_id(&mut self.post_counter)?;
+            let post = Post {
+                id,
+                author,
+                uri,
+                content_hash,
+                title,
+                posted_at: self.env().block_timestamp(),
+            };
+            self.post_map.insert(id, &post);
+            let nth = self.post_count_of(author);
+            self.author_posts.insert((author, nth), &id);
+            self.author_post_counts.insert(author, &(nth + 1));
+            Self::emit_event(
+                Self::env(),
+                Event::ContentPosted(ContentPosted { author, id }),
+            );
+            Ok(id)
+        }
+
+        #[ink(message)]
+        pub fn get_post(&self, id: u32) -> Option<Post> {
+            self.post_map.get(id)
+        }
+
+        /// Number of posts ever published by `author`, including deleted ones.
+        #[ink(message)]
+        pub fn post_count_of(&self, author: AccountId) -> u32 {
+            self.author_post_counts.get(author).unwrap_or(0)
+        }
+
+        /// Returns the posts of `author`, newest first. Deleted posts are skipped.
+        #[ink(message)]
+        pub fn posts_by_author(&self, author: AccountId, offset: u32, limit: u32) -> Vec<Post> {
+            let count = self.post_count_of(author);
+            page(count, offset, limit)
+                .filter_map(|i| self.author_posts.get((author, count - 1 - i)))
+                .filter_map(|id| self.get_post(id))
+                .collect()
+        }
+
+        /// Total pizzas tipped to post `id`.
+        #[ink(message)]
+        pub fn pizzas_for_post(&self, id: u32) -> u64 {
+            self.pizzas_per_post.get(id).unwrap_or(0)
+        }
+
+        /// Returns the tips sent for post `id`, newest first.
+        #[ink(message)]
+        pub fn tips_for_post(&self, id: u32, offset: u32, limit: u32) -> Vec<TipView> {
+            let count = self.post_tip_counts.get(id).unwrap_or(0);
+            page(count, offset, limit)
+                .filter_map(|i| self.post_tips.get((id, count - 1 - i)))
+                .filter_map(|tip_id| self.get_tip(tip_id))
+                .filter(TipView::is_listed)
+                .collect()
+        }
+
+        fn ensure_post_of(&self, content_id: u32, to: AccountId) -> Result<(), TipperError> {
+            let post = self.get_post(content_id).ok_or(TipperError::PostNotFound)?;
+            if post.author != to {
+                return Err(TipperError::PostNotByRecipient);
+            }
+            Ok(())
+        }
+
+        fn record_post_tip(
+            &mut self,
+            content_id: u32,
+            tip_id: u32,
+            n_pizzas: u32,
+        ) -> Result<(), TipperError> {
+            let pizzas = self
+                .pizzas_for_post(content_id)
+                .checked_add(n_pizzas.into())
+                .ok_or(TipperError::Overflow)?;
+            self.pizzas_per_post.insert(content_id, &pizzas);
+            let nth = self.post_tip_counts.get(content_id).unwrap_or(0);
+            self.post_tips.insert((content_id, nth), &tip_id);
+            self.post_tip_counts.insert(content_id, &(nth + 1));
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn edit_post(
+            &mut self,
+            id: u32,
+            uri: String,
+            content_hash: Hash,
+            title: String,
+        ) -> Result<(), TipperError> {
+            let mut post = self.own_post(id)?;
+            validate_post(&uri, &title)?;
+            post.uri = uri;
+            post.content_hash = content_hash;
+            post.title = title;
+            self.post_map.insert(id, &post);
+            Self::emit_event(
+                Self::env(),
+                Event::ContentEdited(ContentEdited {
+                    author: post.author,
+                    id,
+                }),
+            );
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn delete_post(&mut self, id: u32) -> Result<(), TipperError> {
+            let post = self.own_post(id)?;
+            self.post_map.remove(id);
+            Self::emit_event(
+                Self::env(),
+                Event::ContentDeleted(ContentDeleted {
+                    author: post.author,
+                    id,
+                }),
+            );
+            Ok(())
+        }
+
+        fn own_post(&self, id: u32) -> Result<Post, TipperError> {
+            let post = self.get_post(id).ok_or(TipperError::PostNotFound)?;
+            if post.author != self.env().caller() {
+                return Err(TipperError::NotPostAuthor);
+            }
+            Ok(post)
+        }
+
+        /// Registers the caller as a creator under `handle`, lowercased. Tips sent to the
+        /// caller are credited to `payout_account` from then on.
+        #[ink(message)]
+        pub fn register_creator(
+            &mut self,
+            handle: String,
+            profile_uri: String,
+            payout_account: AccountId,
+        ) -> Result<(), TipperError> {
+            let account = self.env().caller();
+            if self.creators.contains(account) {
+                return Err(TipperError::AlreadyRegistered);
+            }
+            let handle = normalize_handle(&handle)?;
+            validate_profile_uri(&profile_uri)?;
+            if self.handles.contains(&handle) {
+                return Err(TipperError::HandleTaken);
+            }
+            self.handles.insert(&handle, &account);
+            let creator = Creator {
+                account,
+                handle: handle.clone(),
+                profile_uri,
+                payout_account,
+            };
+            self.creators.insert(account, &creator);
+            Self::emit_event(
+                Self::env(),
+                Event::CreatorRegistered(CreatorRegistered { account, handle }),
+            );
+            Ok(())
+        }
+
+        /// Updates the caller's profile; the handle stays the same.
+        #[ink(message)]
+        pub fn update_profile(
+            &mut self,
+            profile_uri: String,
+            payout_account: AccountId,
+        ) -> Result<(), TipperError> {
+            let account = self.env().caller();
+            let mut creator = self.profile_of(account).ok_or(TipperError::CreatorNotFound)?;
+            validate_profile_uri(&profile_uri)?;
+            creator.profile_uri = profile_uri;
+            creator.payout_account = payout_account;
+            self.creators.insert(account, &creator);
+            Self::emit_event(
+                Self::env(),
+                Event::ProfileUpdated(ProfileUpdated { account }),
+            );
+            Ok(())
+        }
+
+        /// Returns the account registered under `handle`.
+        #[ink(message)]
+        pub fn creator_of(&self, handle: String) -> Option<AccountId> {
+            self.handles.get(&normalize_handle(&handle).ok()?)
+        }
+
+        #[ink(message)]
+        pub fn profile_of(&self, account: AccountId) -> Option<Creator> {
+            self.creators.get(account)
+        }
+
+        /// Account that receives the tips sent to `to`.
+        fn payout_account_of(&self, to: AccountId) -> AccountId {
+            self.profile_of(to)
+                .map_or(to, |creator| creator.payout_account)
+        }
+
+        /// Hides a tip received by the caller from listings. The message is blanked in
+        /// queries but kept, so the tip can be unhidden. Redacted tips can be hidden too;
+        /// deleted ones are already left out of listings.
+        #[ink(message)]
+        pub fn hide_tip(&mut self, id: u32) -> Result<(), TipperError> {
+            self.received_tip(id)?;
+            if self.hidden_tips.contains(id)
+                || self.moderation_of(id) == Some(Moderation::Deleted)
+            {
+                return Err(TipperError::InvalidVisibilityChange);
+            }
+            self.hidden_tips.insert(id, &());
+            self.emit_visibility_change(id);
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn unhide_tip(&mut self, id: u32) -> Result<(), TipperError> {
+            self.received_tip(id)?;
+            if !self.hidden_tips.contains(id) {
+                return Err(TipperError::InvalidVisibilityChange);
+            }
+            self.hidden_tips.remove(id);
+            self.emit_visibility_change(id);
+            Ok(())
+        }
+
+        /// Removes the message of tip `id`; the tip stays listed.
+        #[ink(message)]
+        pub fn redact_tip(&mut self, id: u32) -> Result<(), TipperError> {
+            self.ensure_role(Role::Moderator)?;
+            self.moderate_tip(id, Moderation::Redacted)
+        }
+
+        /// Removes the message of tip `id` and leaves the tip out of listings. The payment
+        /// record is kept and still returned by `get_tip`.
+        #[ink(message)]
+        pub fn delete_tip(&mut self, id: u32) -> Result<(), TipperError> {
+            self.ensure_role(Role::Moderator)?;
+            self.moderate_tip(id, Moderation::Deleted)
+        }
+
+        /// Reports tip `id` to moderators. Each account can report a tip once; `reason`
+        /// follows the rules of tip messages and is only emitted, not stored.
+        #[ink(message)]
+        pub fn report_tip(&mut self, id: u32, reason: String) -> Result<(), TipperError> {
+            if !self.tip_map.contains(id) {
+                return Err(TipperError::TipNotFound);
+            }
+            self.validate_message(&reason)?;
+            let reporter = self.env().caller();
+            if self.tip_reports.contains((id, reporter)) {
+                return Err(TipperError::AlreadyReported);
+            }
+            self.tip_reports.insert((id, reporter), &());
+            self.report_counts
+                .insert(id, &(self.report_count_of(id).saturating_add(1)));
+            Self::emit_event(
+                Self::env(),
+                Event::TipReported(TipReported { id, reporter, reason }),
+            );
+            Ok(())
+        }
+
+        /// Stores the caller's reply to a tip they received; each tip takes one reply.
+        /// The reply follows the rules of tip messages, including the per-byte surcharge.
+        #[ink(message, payable)]
+        pub fn reply_to_tip(&mut self, tip_id: u32, message: String) -> Result<(), TipperError> {
+            let tip = self.received_tip(tip_id)?;
+            if self.tip_replies.contains(tip_id) {
+                return Err(TipperError::AlreadyReplied);
+            }
+            let surcharge = self.message_surcharge(&message)?;
+            let transfered_amount = self.env().transferred_value();
+            if transfered_amount < surcharge {
+                return Err(TipperError::InsufficientAmount(surcharge));
+            }
+            self.credit_treasury(surcharge)?;
+            self.tip_replies.insert(tip_id, &message);
+            Self::emit_event(
+                Self::env(),
+                Event::TipReplied(TipReplied {
+                    tipper: tip.from,
+                    creator: tip.to,
+                    id: tip_id,
+                    message,
+                }),
+            );
+            self.refund_change(tip.to, transfered_amount - surcharge);
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn report_count_of(&self, id: u32) -> u32 {
+            self.report_counts.get(id).unwrap_or(0)
+        }
+
+        /// Visibility of tip `id`: `Deleted` takes precedence over `Hidden`, which takes
+        /// precedence over `Redacted`.
+        #[ink(message)]
+        pub fn visibility_of(&self, id: u32) -> Visibility {
+            match self.moderation_of(id) {
+                Some(Moderation::Deleted) => Visibility::Deleted,
+                _ if self.hidden_tips.contains(id) => Visibility::Hidden,
+                Some(Moderation::Redacted) => Visibility::Redacted,
+                None => Visibility::Visible,
+            }
+        }
+
+        fn moderation_of(&self, id: u32) -> Option<Moderation> {
+            self.tip_visibility.get(id)
+        }
+
+        fn received_tip(&self, id: u32) -> Result<Tip, TipperError> {
+            let tip = self.get_by_id(id).ok_or(TipperError::TipNotFound)?;
+            if tip.to != self.env().caller() {
+                return Err(TipperError::NotTipRecipient);
+            }
+            Ok(tip)
+        }
+
+        fn moderate_tip(&mut self, id: u32, moderation: Moderation) -> Result<(), TipperError> {
+            let mut tip = self.get_by_id(id).ok_or(TipperError::TipNotFound)?;
+            let current = self.moderation_of(id);
+            if current == Some(moderation) || current == Some(Moderation::Deleted) {
+                return Err(TipperError::InvalidVisibilityChange);
+            }
+            tip.message = String::new();
+            self.tip_map.insert(id, &tip);
+            self.tip_visibility.insert(id, &moderation);
+            self.emit_visibility_change(id);
+            Ok(())
+        }
+
+        /// Emits the visibility of tip `id` resulting from the caller's change.
+        fn emit_visibility_change(&self, id: u32) {
+            Self::emit_event(
+                Self::env(),
+                Event::TipModerated(TipModerated {
+                    id,
+                    by: self.env().caller(),
+                    visibility: self.visibility_of(id),
+                }),
+            );
+        }
+
+        #[ink(message)]
+        pub fn highlight_price(&self) -> Balance {
+            self.highlight_price.get_or_default()
+        }
+
+        #[ink(message)]
+        pub fn set_highlight_price(&mut self, highlight_price: Balance) -> Result<(), TipperError> {
+            self.ensure_role(Role::PriceSetter)?;
+            self.highlight_price.set(&highlight_price);
+            Ok(())
+        }
+
+        /// Highlights one of the caller's own tips for `highlight_price`, which is collected
+        /// with the fees; any surplus is refunded.
+        #[ink(message, payable)]
+        pub fn highlight_my_tip(&mut self, tip_id: u32) -> Result<(), TipperError> {
+            let (caller, tip) = self.own_tip(tip_id)?;
+            let transferred = self.env().transferred_value();
+            let highlight_price = self.highlight_price();
+            if transferred < highlight_price {
+                return Err(TipperError::InsufficientAmount(highlight_price));
+            }
+            self.highlight_tip(tip_id, &tip)?;
+            self.credit_treasury(highlight_price)?;
+            self.refund_change(caller, transferred - highlight_price);
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn unhighlight_my_tip(&mut self, tip_id: u32) -> Result<(), TipperError> {
+            let (caller, _tip) = self.own_tip(tip_id)?;
+            let highlighted = self.highlighted_pizzas_ref()?;
+            let call_result = highlighted.call().get_highlighted_pizzas(caller).try_invoke();
+            let highlighted_tip = match call_result {
+                Ok(Ok(highlighted_tip)) => highlighted_tip,
+                Ok(Err(_)) | Err(_) => return Err(TipperError::HighlightUnavailable),
+            };
+            if highlighted_tip != Some(tip_id) {
+                return Err(TipperError::HighlightError(
+                    HighlightedPizzasError::HighlightNotFound,
+                ));
+            }
+            self.delete_tip_highlight(caller)
+        }
+
+        /// Checks highlighting is available and returns the caller with their tip `tip_id`.
+        fn own_tip(&self, tip_id: u32) -> Result<(AccountId, Tip), TipperError> {
+            self.ensure_not_paused(PauseScope::Highlighting)?;
+            let caller = self.env().caller();
+            let tip = self.get_by_id(tip_id).ok_or(TipperError::TipNotFound)?;
+            if tip.from != caller {
+                return Err(TipperError::NotTipAuthor);
+            }
+            self.highlighted_pizzas().ok_or(TipperError::HighlightUnavailable)?;
+            Ok((caller, tip))
+        }
+
+        fn highlighted_pizzas_ref(&self) -> Result<contract_ref!(HighlightPizzas), TipperError> {
+            self.highlighted_pizzas()
+                .map(Into::into)
+                .ok_or(TipperError::HighlightUnavailable)
+        }
+
+        fn highlight_tip(&self, id: u32, tip: &Tip) -> Result<(), TipperError> {
+            let mut highlighted = self.highlighted_pizzas_ref()?;
+            let call_result = highlighted
+                .call_mut()
+                .add(tip.from, tip.to, id, tip.pizzas)
+                .try_invoke();
+            match call_result {
+                Ok(Ok(Ok(_unit))) => Ok(()),
+                Ok(Ok(Err(contract_call_error))) => {
+                    Err(TipperError::HighlightError(contract_call_error))
+                }
+                Ok(Err(_)) | Err(_) => Err(TipperError::HighlightUnavailable),
+            }
+        }
+
+        fn delete_tip_highlight(&self, from: AccountId) -> Result<(), TipperError> {
+            let mut highlighted = self.highlighted_pizzas_ref()?;
+            let call_result = highlighted.call_mut().delete_tip_by_author(from).try_invoke();
+            match call_result {
+                Ok(Ok(Ok(_unit))) => Ok(()),
+                Ok(Ok(Err(contract_call_error))) => {
+                    Err(TipperError::HighlightError(contract_call_error))
+                }
+                Ok(Err(_)) | Err(_) => Err(TipperError::HighlightUnavailable),
+            }
+        }
+
+        fn emit_event<EE>(emitter: EE, event: Event)
+        where
+            EE: EmitEvent<Tipper>,
+        {
+            emitter.emit_event(event);
+        }
+    }
+
+    fn storage_key_of<T: StorageKey>(_: &T) -> Key {
+        T::KEY
+    }
+
+    /// Returns the current value of `counter` and advances it.
+    fn next_id<K: StorageKey>(counter: &mut Lazy<u32, K>) -> Result<u32, TipperError> {
+        let id = counter.get_or_default();
+        counter.set(&id.checked_add(1).ok_or(TipperError::Overflow)?);
+        Ok(id)
+    }
+
+    /// Whether `uri` is non-empty, at most `MAX_URI_LEN` bytes and free of whitespace and
+    /// control characters.
+    fn is_valid_uri(uri: &str) -> bool {
+        !uri.is_empty()
+            && uri.len() <= MAX_URI_LEN
+            && !uri.chars().any(|c| c.is_whitespace() || c.is_control())
+    }
+
+    /// Lowercases `handle` after checking its length and characters against `MAX_HANDLE_LEN`.
+    fn normalize_handle(handle: &str) -> Result<String, TipperError> {
+        let allowed = |c: u8| c.is_ascii_alphanumeric() || matches!(c, b'_' | b'-' | b'.');
+        if handle.is_empty() || handle.len() > MAX_HANDLE_LEN || !handle.bytes().all(allowed) {
+            return Err(TipperError::InvalidHandle);
+        }
+        Ok(handle.to_ascii_lowercase())
+    }
+
+    /// Profile URIs are optional, so unlike post URIs they may be empty.
+    fn validate_profile_uri(uri: &str) -> Result<(), TipperError> {
+        if !uri.is_empty() && !is_valid_uri(uri) {
+            return Err(TipperError::InvalidProfileUri);
+        }
+        Ok(())
+    }
+
+    fn validate_post(uri: &str, title: &str) -> Result<(), TipperError> {
+        if !is_valid_uri(uri) || title.len() > MAX_TITLE_LEN || title.chars().any(char::is_control)
+        {
+            return Err(TipperError::InvalidPost);
+        }
+        Ok(())
+    }
+
+    /// Indices `offset..offset + limit` of a list of `count` entries, clamped to the list
+    /// and to `MAX_PAGE_SIZE`.
+    fn page(count: u32, offset: u32, limit: u32) -> core::ops::Range<u32> {
+        let start = offset.min(count);
+        let end = start.saturating_add(limit.min(MAX_PAGE_SIZE)).min(count);
+        start..end
+    }
+
+    /// Moves `account` to its place in `leaderboard` for its new `total`, which never
+    /// decreases, keeping at most `LEADERBOARD_SIZE` entries. Returns whether the
+    /// leaderboard changed.
+    fn rank(leaderboard: &mut Leaderboard, account: AccountId, total: u64) -> bool {
+        if let Some(position) = leaderboard.iter().position(|(ranked, _)| *ranked == account) {
+            leaderboard.remove(position);
+        }
+        // ties keep the account that got there first ahead
+        let position = leaderboard
+            .iter()
+            .position(|(_, ranked_total)| *ranked_total < total)
+            .unwrap_or(leaderboard.len());
+        if position >= LEADERBOARD_SIZE {
+            return false;
+        }
+        leaderboard.insert(position, (account, total));
+        leaderboard.truncate(LEADERBOARD_SIZE);
+        true
+    }
+
+    /// Vouchers of `channel` can be redeemed until this time.
+    fn redeem_deadline(channel: &Channel) -> Timestamp {
+        channel
+            .closing_at
+            .map_or(channel.expires_at, |closing_at| closing_at.min(channel.expires_at))
+    }
+
+    /// Validates the recipients of a split tip and returns their weights.
+    fn split_weights(recipients: &[(AccountId, u16)]) -> Result<Vec<u16>, TipperError> {
+        if recipients.is_empty() || recipients.len() > MAX_SPLIT_RECIPIENTS as usize {
+            return Err(TipperError::InvalidSplit);
+        }
+        for (i, (to, weight)) in recipients.iter().enumerate() {
+            if *weight == 0 || recipients[..i].iter().any(|(other, _)| other == to) {
+                return Err(TipperError::InvalidSplit);
+            }
+        }
+        Ok(recipients.iter().map(|(_, weight)| *weight).collect())
+    }
+
+    /// Divides `total` in proportion to `weights` by largest remainder: the rounding dust
+    /// goes one unit each to the shares with the largest fractional parts, earlier shares
+    /// first on ties.
+    fn split_by_weight(total: u128, weights: &[u16]) -> Vec<u128> {
+        let total_weight: u128 = weights.iter().map(|weight| u128::from(*weight)).sum();
+        // total * weight can't overflow: weights are at most u16::MAX
+        let (mut shares, remainders): (Vec<u128>, Vec<u128>) = weights
+            .iter()
+            .map(|weight| {
+                let weight = u128::from(*weight);
+                let rest = (total % total_weight) * weight;
+                (
+                    (total / total_weight) * weight + rest / total_weight,
+                    rest % total_weight,
+                )
+            })
+            .unzip();
+        // the dust is below the number of shares
+        let dust = (total - shares.iter().sum::<u128>()) as usize;
+        let mut order: Vec<usize> = (0..shares.len()).collect();
+        order.sort_by(|a, b| remainders[*b].cmp(&remainders[*a]));
+        for index in order.into_iter().take(dust) {
+            shares[index] += 1;
+        }
+        shares
+    }
+
+    #[ink(event)]
+    pub struct PizzaSent {
+        #[ink(topic)]
+        from: AccountId,
+        to: AccountId,
+        id: u32,
+        pizzas: u32,
+        //paid to the recipient and to the treasury
+        amount: Balance,
+        fee: Balance,
+        #[ink(topic)]
+        content_id: Option<u32>,
+    }
+
+    #[ink(event)]
+    pub struct PizzaSplitSent {
+        #[ink(topic)]
+        from: AccountId,
+        #[ink(topic)]
+        group_id: u32,
+        recipients: Vec<AccountId>,
+        //tips of the group are numbered from first_id, in recipient order
+        first_id: u32,
+        pizzas: u32,
+        amount: Balance,
+        fee: Balance,
+    }
+
+    #[ink(event)]
+    pub struct Subscribed {
+        id: u32,
+        #[ink(topic)]
+        subscriber: AccountId,
+        #[ink(topic)]
+        creator: AccountId,
+        periods: u32,
+        amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct SubscriptionClaimed {
+        id: u32,
+        #[ink(topic)]
+        creator: AccountId,
+        periods: u32,
+        amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct SubscriptionCancelled {
+        id: u32,
+        #[ink(topic)]
+        subscriber: AccountId,
+        refund: Balance,
+    }
+
+    #[ink(event)]
+    pub struct ChannelOpened {
+        id: u32,
+        #[ink(topic)]
+        tipper: AccountId,
+        #[ink(topic)]
+        creator: AccountId,
+        deposit: Balance,
+        expires_at: Timestamp,
+    }
+
+    #[ink(event)]
+    pub struct VoucherRedeemed {
+        id: u32,
+        #[ink(topic)]
+        creator: AccountId,
+        cumulative_amount: Balance,
+        amount: Balance,
+        fee: Balance,
+    }
+
+    #[ink(event)]
+    pub struct ChannelClosing {
+        id: u32,
+        closing_at: Timestamp,
+    }
+
+    #[ink(event)]
+    pub struct ChannelSettled {
+        id: u32,
+        refund: Balance,
+    }
+
+    #[ink(event)]
+    pub struct TipModerated {
+        #[ink(topic)]
+        id: u32,
+        by: AccountId,
+        visibility: Visibility,
+    }
+
+    #[ink(event)]
+    pub struct TipReplied {
+        #[ink(topic)]
+        tipper: AccountId,
+        #[ink(topic)]
+        creator: AccountId,
+        id: u32,
+        message: String,
+    }
+
+    #[ink(event)]
+    pub struct TipReported {
+        #[ink(topic)]
+        id: u32,
+        reporter: AccountId,
+        reason: String,
+    }
+
+    #[ink(event)]
+    pub struct ChangeRefunded {
+        #[ink(topic)]
+        to: AccountId,
+        amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct EarningsWithdrawn {
+        #[ink(topic)]
+        account: AccountId,
+        amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct OwnershipTransferStarted {
+        #[ink(topic)]
+        previous_owner: AccountId,
+        #[ink(topic)]
+        new_owner: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct OwnershipTransferred {
+        #[ink(topic)]
+        previous_owner: AccountId,
+        #[ink(topic)]
+        new_owner: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct RoleGranted {
+        role: Role,
+        #[ink(topic)]
+        account: AccountId,
+        by: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct RoleRevoked {
+        role: Role,
+        #[ink(topic)]
+        account: AccountId,
+        by: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct Paused {
+        scope: PauseScope,
+        #[ink(topic)]
+        by: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct Unpaused {
+        scope: PauseScope,
+        #[ink(topic)]
+        by: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct Upgraded {
+        code_hash: Hash,
+    }
+
+    #[ink(event)]
+    pub struct Migrated {
+        from_version: u8,
+        to_version: u8,
+    }
+
+    #[ink(event)]
+    pub struct OracleFallback {
+        reason: TipperError,
+        price: Balance,
+    }
+
+    #[ink(event)]
+    pub struct ContentPosted {
+        #[ink(topic)]
+        author: AccountId,
+        id: u32,
+    }
+
+    #[ink(event)]
+    pub struct ContentEdited {
+        #[ink(topic)]
+        author: AccountId,
+        id: u32,
+    }
+
+    #[ink(event)]
+    pub struct ContentDeleted {
+        #[ink(topic)]
+        author: AccountId,
+        id: u32,
+    }
+
+    #[ink(event)]
+    pub struct CreatorRegistered {
+        #[ink(topic)]
+        account: AccountId,
+        handle: String,
+    }
+
+    #[ink(event)]
+    pub struct ProfileUpdated {
+        #[ink(topic)]
+        account: AccountId,
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use ink::env::{
+            test::{
+                default_accounts, get_account_balance, recorded_events,
+                DefaultAccounts, EmittedEvent,
+            },
+        };
+        use ink::primitives::AccountId;
+        use scale::Decode;
+        fn get_test_accts() -> DefaultAccounts<ink::env::DefaultEnvironment> {
+            default_accounts::<ink::env::DefaultEnvironment>()
+        }
+        fn get_balance(acct_id: AccountId) -> Balance {
+            get_account_balance::<ink::env::DefaultEnvironment>(acct_id)
+                .expect("can't get account balance")
+        }
+        fn set_from(from: AccountId) {
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(from);
+        }
+
+        fn tip_from_alice(instance: &mut Tipper) -> Tip {
+            let accts = get_test_accts();
+            let alice = accts.alice;
+            let bob = accts.bob;
+            let msg: ink::prelude::string::String = "dummy".into();
+            let expected_tip = Tip {
+                from: alice,
+                to: bob,
+                pizzas: 1,
+                message: msg.clone(),
+                content_id: None,
+            };
+            set_from(alice);
+            assert!(instance.tip(msg, bob, 1, 0, None).is_ok(), "tipping expected");
+            expected_tip
+        }
+
+        const PRICE_PER_PIZZA: u128 = 7;
+
+        #[ink::test]
+        fn constructor_works() {
+            let tipper = Tipper::free();
+            assert_eq!(tipper.price_per_pizza(), 0);
+        }
+
+        #[ink::test]
+        fn pizza_msg_test() {
+            let accts = get_test_accts();
+            let alice = accts.alice;
+            let bob = accts.bob;
+            let mut tipper = Tipper::free();
+            let msg: ink::prelude::string::String = "dummy".into();
+            set_from(alice);
+            let _executed_tip = tipper.tip(msg.clone(), accts.bob, 1, 0, None);
+            let expected_tip = Tip {
+                from: alice,
+                to: bob,
+                pizzas: 1,
+                message: "dummy".into(),
+                content_id: None,
+            };
+            assert_eq!(tipper.get_by_id(0).unwrap().message, expected_tip.message);
+        }
+
+        #[ink::test]
+        fn event_on_tip() {
+            let mut instance = Tipper::free();
+            let tip = tip_from_alice(&mut instance);
+            let recorded_events = recorded_events().collect::<Vec<_>>();
+            assert_expected_tip_event(&recorded_events[0], tip.to, tip.from, 0, tip.pizzas);
+        }
+
+        #[ink::test]
+        fn overpayment_is_refunded_as_change() {
+            let accts = get_test_accts();
+            let mut instance = Tipper::new(1, None, PRICE_PER_PIZZA, 0, accts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(20);
+            let before = get_balance(accts.charlie);
+            set_from(accts.charlie);
+            assert!(instance.tip("dummy".into(), accts.bob, 2, 1, None).is_ok());
+            assert_eq!(instance.earnings_of(accts.bob), 2 * PRICE_PER_PIZZA + 1);
+            assert_eq!(get_balance(accts.charlie), before + 5);
+
+            let recorded_events = recorded_events().collect::<Vec<_>>();
+            let decoded_event = decode_event(&recorded_events[1]);
+            assert!(matches!(
+                decoded_event,
+                Event::ChangeRefunded(ChangeRefunded { to, amount: 5 }) if to == accts.charlie
+            ));
+        }
+
+        #[ink::test]
+        fn fee_is_split_to_treasury() {
+            let accts = get_test_accts();
+            let mut instance = Tipper::new(1, None, 1_000, 250, accts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(3_000);
+            set_from(accts.charlie);
+            assert!(instance.tip("dummy".into(), accts.bob, 3, 0, None).is_ok());
+            assert_eq!(instance.earnings_of(accts.bob), 2_925);
+            assert_eq!(instance.treasury_balance(), 75);
+
+            let recorded_events = recorded_events().collect::<Vec<_>>();
+            let decoded_event = decode_event(&recorded_events[0]);
+            assert!(matches!(
+                decoded_event,
+                Event::PizzaSent(PizzaSent { amount: 2_925, fee: 75, .. })
+            ));
+
+            assert_eq!(
+                instance.set_fee_bps(100),
+                Err(TipperError::MissingRole(Role::Admin))
+            );
+            set_from(accts.alice);
+            assert_eq!(instance.set_fee_bps(10_001), Err(TipperError::InvalidFee));
+            assert_eq!(instance.set_fee_bps(100), Ok(()));
+            let before = get_balance(accts.django);
+            set_from(accts.django);
+            assert_eq!(instance.withdraw_fees(), Ok(75));
+            assert_eq!(get_balance(accts.django), before + 75);
+            assert_eq!(instance.treasury_balance(), 0);
+        }
+
+        #[ink::test]
+        fn fee_math_is_overflow_checked() {
+            let accts = get_test_accts();
+            let mut instance = Tipper::new(1, None, u128::MAX / 2, 100, accts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(u128::MAX);
+            assert_eq!(
+                instance.tip("dummy".into(), accts.bob, 3, 0, None),
+                Err(TipperError::Overflow)
+            );
+            // a payment above u128::MAX / BPS_DENOMINATOR still gets its fee taken
+            let payment = u128::MAX / 2;
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(payment);
+            assert!(instance.tip("dummy".into(), accts.bob, 1, 0, None).is_ok());
+            assert_eq!(instance.treasury_balance(), payment / 100);
+            assert_eq!(instance.earnings_of(accts.bob), payment - payment / 100);
+        }
+
+        #[ink::test]
+        fn ownership_transfer_is_two_step() {
+            let accts = get_test_accts();
+            set_from(accts.alice);
+            let mut instance = Tipper::free();
+            assert_eq!(instance.owner(), accts.alice);
+
+            set_from(accts.bob);
+            assert_eq!(
+                instance.transfer_ownership(accts.bob),
+                Err(TipperError::Unauthorized)
+            );
+            assert_eq!(instance.terminate_contract(), Err(TipperError::Unauthorized));
+
+            set_from(accts.alice);
+            assert_eq!(instance.transfer_ownership(accts.bob), Ok(()));
+            assert_eq!(instance.owner(), accts.alice);
+            assert_eq!(instance.pending_owner(), Some(accts.bob));
+
+            set_from(accts.charlie);
+            assert_eq!(instance.accept_ownership(), Err(TipperError::Unauthorized));
+            set_from(accts.bob);
+            assert_eq!(instance.accept_ownership(), Ok(()));
+            assert_eq!(instance.owner(), accts.bob);
+            assert_eq!(instance.pending_owner(), None);
+        }
+
+        #[ink::test]
+        fn roles_guard_configuration() {
+            let accts = get_test_accts();
+            set_from(accts.alice);
+            let mut instance = Tipper::free();
+
+            set_from(accts.bob);
+            assert_eq!(
+                instance.set_price_per_pizza(5),
+                Err(TipperError::MissingRole(Role::PriceSetter))
+            );
+            assert_eq!(
+                instance.set_pizza_oracle(Some(accts.eve)),
+                Err(TipperError::MissingRole(Role::Admin))
+            );
+
+            set_from(accts.alice);
+            assert_eq!(instance.grant_role(Role::Admin, accts.bob), Ok(()));
+            set_from(accts.bob);
+            assert_eq!(instance.grant_role(Role::PriceSetter, accts.charlie), Ok(()));
+            assert_eq!(
+                instance.grant_role(Role::Admin, accts.charlie),
+                Err(TipperError::Unauthorized)
+            );
+            assert_eq!(instance.set_highlighted_pizzas(None), Ok(()));
+            assert_eq!(instance.highlighted_pizzas(), None);
+
+            set_from(accts.charlie);
+            assert_eq!(instance.set_price_per_pizza(5), Ok(()));
+            assert_eq!(instance.price_per_pizza(), 5);
+
+            set_from(accts.bob);
+            assert_eq!(instance.revoke_role(Role::PriceSetter, accts.charlie), Ok(()));
+            assert!(!instance.has_role(Role::PriceSetter, accts.charlie));
+            assert!(instance.has_role(Role::Moderator, accts.alice));
+            assert_eq!(recorded_events().count(), 3);
+        }
+
+        #[ink::test]
+        fn pausing_blocks_each_scope_separately() {
+            let accts = get_test_accts();
+            set_from(accts.alice);
+            let mut instance = Tipper::free();
+            set_from(accts.bob);
+            assert_eq!(
+                instance.pause(PauseScope::Tipping),
+                Err(TipperError::MissingRole(Role::Admin))
+            );
+
+            set_from(accts.alice);
+            assert_eq!(instance.pause(PauseScope::Tipping), Ok(()));
+            assert!(instance.is_paused(PauseScope::Tipping));
+            assert!(!instance.is_paused(PauseScope::Withdrawals));
+            assert_eq!(
+                instance.tip("dummy".into(), accts.bob, 1, 0, None),
+                Err(TipperError::Paused)
+            );
+
+            assert_eq!(instance.unpause(PauseScope::Tipping), Ok(()));
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(5);
+            assert!(instance.tip("dummy".into(), accts.bob, 1, 5, None).is_ok());
+
+            assert_eq!(instance.pause(PauseScope::Withdrawals), Ok(()));
+            set_from(accts.bob);
+            assert_eq!(instance.withdraw_all(), Err(TipperError::Paused));
+            assert_eq!(instance.withdraw(1), Err(TipperError::Paused));
+            set_from(accts.alice);
+            assert_eq!(instance.unpause(PauseScope::Withdrawals), Ok(()));
+            set_from(accts.bob);
+            assert_eq!(instance.withdraw_all(), Ok(5));
+        }
+
+        #[ink::test]
+        fn migrate_rewrites_legacy_tips() {
+            let accts = get_test_accts();
+            set_from(accts.alice);
+            let mut instance = Tipper::free();
+            let key = storage_key_of(&instance.tip_map);
+            for id in 0..3u32 {
+                let legacy = (accts.alice, accts.bob, id + 1, String::from("legacy"));
+                ink::env::set_contract_storage(&(key, id), &legacy);
+            }
+            instance.id_counter.set(&3);
+            instance.storage_version.set(&0);
+
+            assert_eq!(
+                instance.tip("new".into(), accts.bob, 1, 0, None),
+                Err(TipperError::MigrationPending)
+            );
+            set_from(accts.bob);
+            assert_eq!(
+                instance.migrate(2),
+                Err(TipperError::MissingRole(Role::Admin))
+            );
+            set_from(accts.alice);
+            assert_eq!(instance.migrate(2), Ok(false));
+            assert_eq!(instance.storage_version(), 0);
+            assert_eq!(instance.migrate(2), Ok(true));
+            assert_eq!(instance.storage_version(), STORAGE_VERSION);
+
+            let tip = instance.get_tip(2).unwrap();
+            assert_eq!((tip.from, tip.to, tip.pizzas), (accts.alice, accts.bob, 3));
+            assert_eq!(tip.message, "legacy");
+            assert_eq!(tip.content_id, None);
+            assert!(instance.tip("new".into(), accts.bob, 1, 0, None).is_ok());
+        }
+
+        #[ink::test]
+        fn pizza_oracle_works() {
+            let accts = get_test_accts();
+            set_from(accts.alice);
+            let mut instance = Tipper::free();
+            assert_eq!(instance.set_price_per_pizza(7), Ok(()));
+            assert_eq!(instance.set_oracle_config(3, 80), Ok(()));
+            assert_eq!(instance.oracle_config(), (3, 80));
+            assert_eq!(instance.current_pizza_price(), Ok(7));
+
+            let confident = PizzaPrice {
+                confidence: 90,
+                current_pizza_price: 11,
+            };
+            assert_eq!(instance.price_from_oracle(Ok(confident)), Ok(11));
+            let unsure = PizzaPrice {
+                confidence: 79,
+                current_pizza_price: 11,
+            };
+            assert_eq!(
+                instance.price_from_oracle(Ok(unsure)),
+                Err(TipperError::OracleConfidenceTooLow(79))
+            );
+            assert_eq!(
+                instance.price_from_oracle(Err(TipperError::OracleUnavailable)),
+                Ok(7)
+            );
+            assert_eq!(
+                instance.price_from_oracle(Err(TipperError::OraclePriceNotFound)),
+                Ok(7)
+            );
+            let recorded_events = recorded_events().collect::<Vec<_>>();
+            let decoded_event = decode_event(&recorded_events[0]);
+            assert!(matches!(
+                decoded_event,
+                Event::OracleFallback(OracleFallback {
+                    reason: TipperError::OracleUnavailable,
+                    price: 7
+                })
+            ));
+        }
+
+        #[ink::test]
+        fn tips_by_tipper_keeps_full_history() {
+            let accts = get_test_accts();
+            let mut instance = Tipper::free();
+            for _ in 0..3 {
+                let _tip = tip_from_alice(&mut instance);
+            }
+            set_from(accts.charlie);
+            assert!(instance.tip("other".into(), accts.bob, 1, 0, None).is_ok());
+            let _tip = tip_from_alice(&mut instance);
+
+            assert_eq!(instance.tip_count_of(accts.alice), 4);
+            assert_eq!(instance.tip_count_of(accts.charlie), 1);
+            assert_eq!(instance.tips_by_tipper(accts.alice, 0, 10), vec![0, 1, 2, 4]);
+            assert_eq!(instance.tips_by_tipper(accts.alice, 1, 2), vec![1, 2]);
+            assert_eq!(instance.tips_by_tipper(accts.alice, 9, 2), Vec::<u32>::new());
+            assert_eq!(instance.tips_by_tipper(accts.charlie, 0, 10), vec![3]);
+            assert_eq!(instance.latest_tip_of(accts.alice).unwrap().id, 4);
+        }
+
+        #[ink::test]
+        fn tips_for_creator_newest_first() {
+            let accts = get_test_accts();
+            let mut instance = Tipper::free();
+            let _tip = tip_from_alice(&mut instance);
+            set_from(accts.charlie);
+            assert!(instance.tip("to django".into(), accts.django, 2, 0, None).is_ok());
+            assert!(instance.tip("second".into(), accts.bob, 3, 0, None).is_ok());
+
+            let received = instance.tips_for_creator(accts.bob, 0, 10);
+            let ids: Vec<u32> = received.iter().map(|tip| tip.id).collect();
+            assert_eq!(ids, vec![2, 0]);
+            assert_eq!(received[0].message, "second");
+            assert_eq!(received[0].from, accts.charlie);
+            assert_eq!(instance.tips_for_creator(accts.bob, 1, 1)[0].id, 0);
+            assert_eq!(instance.received_count_of(accts.django), 1);
+            assert!(instance.tips_for_creator(accts.eve, 0, 10).is_empty());
+        }
+
+        #[ink::test]
+        fn read_api_returns_tip_views() {
+            let accts = get_test_accts();
+            let mut instance = Tipper::free();
+            assert_eq!(instance.get_tip(0), None);
+            assert_eq!(instance.latest_tip_of(accts.alice), None);
+            let tip = tip_from_alice(&mut instance);
+            let expected = TipView {
+                id: 0,
+                from: tip.from,
+                to: tip.to,
+                pizzas: tip.pizzas,
+                message: tip.message,
+                content_id: None,
+                visibility: Visibility::Visible,
+                reply: None,
+            };
+            assert_eq!(instance.get_tip(0), Some(expected.clone()));
+            assert_eq!(instance.latest_tip_of(accts.alice), Some(expected));
+            assert_eq!(instance.price_per_pizza(), 0);
+        }
+
+        #[ink::test]
+        fn new_without_code_hash_leaves_highlights_detached() {
+            let accts = get_test_accts();
+            set_from(accts.alice);
+            let mut instance = Tipper::new(3, None, PRICE_PER_PIZZA, 0, accts.django);
+            assert_eq!(instance.highlighted_pizzas(), None);
+            assert_eq!(instance.version(), 3);
+            assert_eq!(instance.set_highlighted_pizzas(None), Ok(()));
+            assert_eq!(instance.highlighted_pizzas(), None);
+        }
+
+        /// Fails to compile if HighlightedPizzas stops implementing the `HighlightPizzas`
+        /// interface that `highlight_tip` calls through `contract_ref!`.
+        #[ink::test]
+        fn highlight_interface_matches_highlighted_pizzas() {
+            fn assert_implements<T: HighlightPizzas>() {}
+            assert_implements::<HighlightedPizzasRef>();
+            assert_implements::<contract_ref!(HighlightPizzas)>();
+        }
+
+        #[ink::test]
+        fn highlight_my_tip_checks_caller_and_payment() {
+            let accts = get_test_accts();
+            set_from(accts.alice);
+            let mut instance = Tipper::free();
+            assert_eq!(instance.set_highlight_price(10), Ok(()));
+            let _tip = tip_from_alice(&mut instance);
+
+            assert_eq!(instance.highlight_my_tip(1), Err(TipperError::TipNotFound));
+            assert_eq!(
+                instance.highlight_my_tip(0),
+                Err(TipperError::HighlightUnavailable)
+            );
+            assert_eq!(
+                instance.unhighlight_my_tip(0),
+                Err(TipperError::HighlightUnavailable)
+            );
+            // attaching probes the deployment, which the off-chain env can't call
+            instance.highlighted_pizzas.set(&Some(accts.eve));
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(9);
+            assert_eq!(
+                instance.highlight_my_tip(0),
+                Err(TipperError::InsufficientAmount(10))
+            );
+
+            set_from(accts.bob);
+            assert_eq!(instance.highlight_my_tip(0), Err(TipperError::NotTipAuthor));
+            assert_eq!(instance.unhighlight_my_tip(0), Err(TipperError::NotTipAuthor));
+
+            set_from(accts.alice);
+            assert_eq!(instance.pause(PauseScope::Highlighting), Ok(()));
+            assert_eq!(instance.highlight_my_tip(0), Err(TipperError::Paused));
+        }
+
+        #[ink::test]
+        fn posts_validate_uri_and_title() {
+            let accts = get_test_accts();
+            let mut instance = Tipper::free();
+            let hash = Hash::from([0x01; 32]);
+            set_from(accts.bob);
+            let long_uri = format!("ipfs://{}", "a".repeat(MAX_URI_LEN));
+            for (uri, title) in [
+                ("", "title"),
+                ("ipfs://a b", "title"),
+                ("ipfs://a\n", "title"),
+                (long_uri.as_str(), "title"),
+                ("ipfs://a", "line\nbreak"),
+            ] {
+                assert_eq!(
+                    instance.post_content(uri.into(), hash, title.into()),
+                    Err(TipperError::InvalidPost)
+                );
+            }
+            assert_eq!(
+                instance.post_content("ipfs://a".into(), hash, "x".repeat(MAX_TITLE_LEN + 1)),
+                Err(TipperError::InvalidPost)
+            );
+            assert_eq!(instance.post_content("ipfs://a".into(), hash, "".into()), Ok(0));
+            assert_eq!(
+                instance.edit_post(0, "ipfs://a b".into(), hash, "title".into()),
+                Err(TipperError::InvalidPost)
+            );
+            assert_eq!(instance.get_post(0).unwrap().uri, "ipfs://a");
+        }
+
+        #[ink::test]
+        fn creators_post_edit_and_delete_content() {
+            let accts = get_test_accts();
+            let mut instance = Tipper::free();
+            let hash = Hash::from([0x01; 32]);
+            set_from(accts.bob);
+            assert_eq!(instance.post_content("ipfs://a".into(), hash, "first".into()), Ok(0));
+            set_from(accts.charlie);
+            assert_eq!(instance.post_content("ipfs://b".into(), hash, "other".into()), Ok(1));
+            set_from(accts.bob);
+            assert_eq!(instance.post_content("ipfs://c".into(), hash, "second".into()), Ok(2));
+
+            let post = instance.get_post(0).unwrap();
+            assert_eq!((post.author, post.title.as_str()), (accts.bob, "first"));
+            let titles: Vec<String> = instance
+                .posts_by_author(accts.bob, 0, 10)
+                .into_iter()
+                .map(|post| post.title)
+                .collect();
+            assert_eq!(titles, vec!["second", "first"]);
+
+            let new_hash = Hash::from([0x02; 32]);
+            set_from(accts.charlie);
+            assert_eq!(
+                instance.edit_post(0, "ipfs://x".into(), new_hash, "hijack".into()),
+                Err(TipperError::NotPostAuthor)
+            );
+            assert_eq!(instance.delete_post(0), Err(TipperError::NotPostAuthor));
+            set_from(accts.bob);
+            assert_eq!(
+                instance.edit_post(0, "ipfs://a2".into(), new_hash, "first!".into()),
+                Ok(())
+            );
+            assert_eq!(instance.get_post(0).unwrap().content_hash, new_hash);
+            assert_eq!(instance.delete_post(2), Ok(()));
+            assert_eq!(instance.get_post(2), None);
+            assert_eq!(instance.delete_post(2), Err(TipperError::PostNotFound));
+            assert_eq!(instance.posts_by_author(accts.bob, 0, 10).len(), 1);
+            assert_eq!(instance.post_count_of(accts.bob), 2);
+
+            let recorded_events = recorded_events().collect::<Vec<_>>();
+            assert_eq!(recorded_events.len(), 5);
+            let decoded_event = decode_event(&recorded_events[0]);
+            assert!(matches!(
+                decoded_event,
+                Event::ContentPosted(ContentPosted { author, id: 0 }) if author == accts.bob
+            ));
+        }
+
+        #[ink::test]
+        fn tips_for_posts_are_tracked() {
+            let accts = get_test_accts();
+            let mut instance = Tipper::free();
+            let hash = Hash::from([0x01; 32]);
+            set_from(accts.bob);
+            assert_eq!(instance.post_content("ipfs://a".into(), hash, "a".into()), Ok(0));
+            set_from(accts.charlie);
+            assert_eq!(instance.post_content("ipfs://b".into(), hash, "b".into()), Ok(1));
+
+            set_from(accts.alice);
+            assert_eq!(
+                instance.tip("dummy".into(), accts.bob, 1, 0, Some(7)),
+                Err(TipperError::PostNotFound)
+            );
+            assert_eq!(
+                instance.tip("dummy".into(), accts.bob, 1, 0, Some(1)),
+                Err(TipperError::PostNotByRecipient)
+            );
+            assert!(instance.tip("first".into(), accts.bob, 2, 0, Some(0)).is_ok());
+            assert!(instance.tip("plain".into(), accts.bob, 1, 0, None).is_ok());
+            assert!(instance.tip("again".into(), accts.bob, 3, 0, Some(0)).is_ok());
+
+            assert_eq!(instance.pizzas_for_post(0), 5);
+            assert_eq!(instance.pizzas_for_post(1), 0);
+            let tips = instance.tips_for_post(0, 0, 10);
+            let ids: Vec<u32> = tips.iter().map(|tip| tip.id).collect();
+            assert_eq!(ids, vec![2, 0]);
+            assert_eq!(tips[0].content_id, Some(0));
+            assert_eq!(instance.get_tip(1).unwrap().content_id, None);
+
+            let recorded_events = recorded_events().collect::<Vec<_>>();
+            let decoded_event = decode_event(&recorded_events[2]);
+            assert!(matches!(
+                decoded_event,
+                Event::PizzaSent(PizzaSent { content_id: Some(0), id: 0, .. })
+            ));
+        }
+
+        #[ink::test]
+        fn registered_creators_are_paid_to_their_payout_account() {
+            let accts = get_test_accts();
+            let mut instance = Tipper::new(1, None, PRICE_PER_PIZZA, 0, accts.django);
+            set_from(accts.bob);
+            for handle in ["", "bo b", "bob\u{0}", "b\u{f6}b", &"b".repeat(MAX_HANDLE_LEN + 1)] {
+                assert_eq!(
+                    instance.register_creator(handle.into(), "ipfs://bob".into(), accts.eve),
+                    Err(TipperError::InvalidHandle)
+                );
+            }
+            assert_eq!(
+                instance.register_creator("bob".into(), "ipfs://b b".into(), accts.eve),
+                Err(TipperError::InvalidProfileUri)
+            );
+            assert_eq!(
+                instance.register_creator("Bob".into(), "ipfs://bob".into(), accts.eve),
+                Ok(())
+            );
+            assert_eq!(
+                instance.register_creator("bobby".into(), "ipfs://bob".into(), accts.eve),
+                Err(TipperError::AlreadyRegistered)
+            );
+            set_from(accts.charlie);
+            assert_eq!(
+                instance.register_creator("bob".into(), "ipfs://charlie".into(), accts.charlie),
+                Err(TipperError::HandleTaken)
+            );
+            assert_eq!(
+                instance.update_profile("ipfs://charlie".into(), accts.charlie),
+                Err(TipperError::CreatorNotFound)
+            );
+            assert_eq!(
+                instance.register_creator("BOB".into(), "".into(), accts.charlie),
+                Err(TipperError::HandleTaken)
+            );
+            assert_eq!(instance.creator_of("bob".into()), Some(accts.bob));
+            assert_eq!(instance.creator_of("BoB".into()), Some(accts.bob));
+            assert_eq!(instance.creator_of("charlie".into()), None);
+
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(PRICE_PER_PIZZA);
+            assert!(instance.tip("dummy".into(), accts.bob, 1, 0, None).is_ok());
+            assert_eq!(instance.earnings_of(accts.eve), PRICE_PER_PIZZA);
+            assert_eq!(instance.earnings_of(accts.bob), 0);
+            assert_eq!(instance.get_tip(0).unwrap().to, accts.bob);
+
+            set_from(accts.bob);
+            assert_eq!(
+                instance.update_profile("x".repeat(MAX_URI_LEN + 1), accts.bob),
+                Err(TipperError::InvalidProfileUri)
+            );
+            assert_eq!(instance.update_profile("ipfs://bob2".into(), accts.bob), Ok(()));
+            let profile = instance.profile_of(accts.bob).unwrap();
+            assert_eq!(profile.profile_uri, "ipfs://bob2");
+            assert_eq!(profile.handle, "bob");
+            set_from(accts.charlie);
+            assert!(instance.tip("dummy".into(), accts.bob, 1, 0, None).is_ok());
+            assert_eq!(instance.earnings_of(accts.bob), PRICE_PER_PIZZA);
+        }
+
+        #[ink::test]
+        fn split_tips_divide_cost_by_weight() {
+            let accts = get_test_accts();
+            let mut instance = Tipper::new(1, None, 100, 0, accts.django);
+            set_from(accts.alice);
+            assert_eq!(
+                instance.tip_split("collab".into(), Vec::new(), 1),
+                Err(TipperError::InvalidSplit)
+            );
+            assert_eq!(
+                instance.tip_split("collab".into(), vec![(accts.bob, 1), (accts.bob, 1)], 1),
+                Err(TipperError::InvalidSplit)
+            );
+            assert_eq!(
+                instance.tip_split("collab".into(), vec![(accts.bob, 1), (accts.charlie, 0)], 1),
+                Err(TipperError::InvalidSplit)
+            );
+
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(200);
+            let recipients = vec![(accts.bob, 1), (accts.charlie, 1), (accts.eve, 1)];
+            assert_eq!(instance.tip_split("collab".into(), recipients, 2), Ok(0));
+            assert_eq!(instance.earnings_of(accts.bob), 67);
+            assert_eq!(instance.earnings_of(accts.charlie), 67);
+            assert_eq!(instance.earnings_of(accts.eve), 66);
+
+            let tips = instance.split_tips(0);
+            let pizzas: Vec<u32> = tips.iter().map(|tip| tip.pizzas).collect();
+            assert_eq!(pizzas, vec![1, 1, 0]);
+            assert_eq!(tips[2].to, accts.eve);
+            assert_eq!(instance.split_group_of(1), Some(0));
+            assert!(instance.tip("solo".into(), accts.bob, 1, 0, None).is_ok());
+            assert_eq!(instance.split_group_of(3), None);
+
+            let recorded_events = recorded_events().collect::<Vec<_>>();
+            assert_eq!(recorded_events.len(), 3);
+            let decoded_event = decode_event(&recorded_events[0]);
+            assert!(matches!(
+                decoded_event,
+                Event::PizzaSplitSent(PizzaSplitSent { group_id: 0, first_id: 0, amount: 200, .. })
+            ));
+        }
+
+        #[ink::test]
+        fn split_by_weight_is_exact() {
+            assert_eq!(split_by_weight(10, &[1, 2, 2]), vec![2, 4, 4]);
+            assert_eq!(split_by_weight(11, &[1, 2, 2]), vec![2, 5, 4]);
+            assert_eq!(split_by_weight(1, &[1, 3]), vec![0, 1]);
+            assert_eq!(split_by_weight(2, &[1, 1, 1]), vec![1, 1, 0]);
+            assert_eq!(split_by_weight(u128::MAX, &[u16::MAX, 1]).iter().sum::<u128>(), u128::MAX);
+        }
+
+        #[ink::test]
+        fn subscriptions_accrue_and_refund_the_remainder() {
+            let accts = get_test_accts();
+            let mut instance = Tipper::new(1, None, 10, 0, accts.django);
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1_000);
+            set_from(accts.charlie);
+            assert_eq!(
+                instance.subscribe(accts.bob, 2, 0, 4),
+                Err(TipperError::InvalidSubscription)
+            );
+            assert_eq!(
+                instance.subscribe(accts.bob, 2, 100, 4),
+                Err(TipperError::InsufficientAmount(80))
+            );
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(80);
+            assert_eq!(instance.subscribe(accts.bob, 2, 100, 4), Ok(0));
+            assert_eq!(instance.subscriptions_of(accts.charlie, 0, 10)[0].creator, accts.bob);
+            assert_eq!(instance.subscribers_of(accts.bob, 0, 10)[0].subscriber, accts.charlie);
+
+            set_from(accts.bob);
+            assert_eq!(instance.claim_subscription(0), Err(TipperError::NothingToClaim));
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1_250);
+            assert_eq!(instance.claimable_periods(0), 2);
+            assert_eq!(instance.cancel_subscription(0), Err(TipperError::NotSubscriber));
+            assert_eq!(instance.claim_subscription(0), Ok(2));
+            assert_eq!(instance.earnings_of(accts.bob), 40);
+            assert_eq!(instance.get_tip(0).unwrap().pizzas, 4);
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1_320);
+            set_from(accts.charlie);
+            assert_eq!(instance.claim_subscription(0), Err(TipperError::NotSubscribedCreator));
+            set_from(accts.alice);
+            assert_eq!(instance.pause(PauseScope::Withdrawals), Ok(()));
+            set_from(accts.charlie);
+            assert_eq!(instance.cancel_subscription(0), Err(TipperError::Paused));
+            set_from(accts.alice);
+            assert_eq!(instance.unpause(PauseScope::Withdrawals), Ok(()));
+            set_from(accts.charlie);
+            let before = get_balance(accts.charlie);
+            assert_eq!(instance.cancel_subscription(0), Ok(20));
+            assert_eq!(get_balance(accts.charlie), before + 20);
+            assert_eq!(instance.earnings_of(accts.bob), 60);
+            let subscription = instance.get_subscription(0).unwrap();
+            assert!(subscription.cancelled);
+            assert_eq!((subscription.periods, subscription.claimed_periods), (3, 3));
+            assert_eq!(instance.subscription_escrow.get_or_default(), 0);
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(2_000);
+            assert_eq!(instance.cancel_subscription(0), Err(TipperError::SubscriptionCancelled));
+            set_from(accts.bob);
+            assert_eq!(instance.claim_subscription(0), Err(TipperError::SubscriptionCancelled));
+        }
+
+        fn sign_voucher(instance: &Tipper, id: u32, cumulative_amount: Balance) -> [u8; 65] {
+            use secp256k1::{Message, SecretKey, SECP256K1};
+            let key = SecretKey::from_slice(&[0x42; 32]).unwrap();
+            let hash = instance.voucher_hash(id, cumulative_amount);
+            let (recovery_id, signature) = SECP256K1
+                .sign_ecdsa_recoverable(&Message::from_slice(&hash).unwrap(), &key)
+                .serialize_compact();
+            let mut voucher = [0; 65];
+            voucher[..64].copy_from_slice(&signature);
+            voucher[64] = recovery_id.to_i32() as u8;
+            voucher
+        }
+
+        fn voucher_signer() -> AccountId {
+            let key = secp256k1::SecretKey::from_slice(&[0x42; 32]).unwrap();
+            let public_key =
+                secp256k1::PublicKey::from_secret_key(secp256k1::SECP256K1, &key).serialize();
+            let mut signer = [0; 32];
+            ink::env::hash_bytes::<Blake2x256>(&public_key, &mut signer);
+            AccountId::from(signer)
+        }
+
+        #[ink::test]
+        fn channel_vouchers_are_redeemed_cumulatively() {
+            let accts = get_test_accts();
+            let mut instance = Tipper::new(1, None, PRICE_PER_PIZZA, 0, accts.django);
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1_000);
+            set_from(accts.charlie);
+            assert_eq!(
+                instance.open_channel(accts.bob, voucher_signer(), 5_000),
+                Err(TipperError::InvalidChannel)
+            );
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(100);
+            assert_eq!(
+                instance.open_channel(accts.bob, voucher_signer(), 1_000),
+                Err(TipperError::InvalidChannel)
+            );
+            assert_eq!(instance.open_channel(accts.bob, voucher_signer(), 5_000), Ok(0));
+
+            set_from(accts.bob);
+            let voucher = sign_voucher(&instance, 0, 30);
+            assert_eq!(
+                instance.redeem_voucher(0, 40, voucher),
+                Err(TipperError::InvalidVoucher)
+            );
+            assert_eq!(instance.redeem_voucher(0, 30, voucher), Ok(30));
+            assert_eq!(instance.redeem_voucher(0, 30, voucher), Err(TipperError::InvalidVoucher));
+            let voucher = sign_voucher(&instance, 0, 45);
+            assert_eq!(instance.redeem_voucher(0, 45, voucher), Ok(15));
+            assert_eq!(instance.earnings_of(accts.bob), 45);
+            let voucher = sign_voucher(&instance, 0, 101);
+            assert_eq!(
+                instance.redeem_voucher(0, 101, voucher),
+                Err(TipperError::VoucherExceedsDeposit)
+            );
+            set_from(accts.charlie);
+            let voucher = sign_voucher(&instance, 0, 50);
+            assert_eq!(
+                instance.redeem_voucher(0, 50, voucher),
+                Err(TipperError::NotChannelCreator)
+            );
+            assert_eq!(instance.get_channel(0).unwrap().redeemed, 45);
+        }
+
+        #[ink::test]
+        fn channels_close_after_the_challenge_window() {
+            let accts = get_test_accts();
+            let mut instance = Tipper::new(1, None, PRICE_PER_PIZZA, 0, accts.django);
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1_000);
+            set_from(accts.charlie);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(100);
+            let expires_at = 1_000 + 2 * CHANNEL_CHALLENGE_PERIOD_MS;
+            assert_eq!(instance.open_channel(accts.bob, voucher_signer(), expires_at), Ok(0));
+            assert_eq!(instance.reclaim_channel(0), Err(TipperError::ChannelStillOpen));
+            set_from(accts.bob);
+            assert_eq!(instance.start_channel_close(0), Err(TipperError::NotChannelTipper));
+            set_from(accts.charlie);
+            assert_eq!(
+                instance.start_channel_close(0),
+                Ok(1_000 + CHANNEL_CHALLENGE_PERIOD_MS)
+            );
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(
+                CHANNEL_CHALLENGE_PERIOD_MS,
+            );
+            set_from(accts.bob);
+            let voucher = sign_voucher(&instance, 0, 60);
+            assert_eq!(instance.redeem_voucher(0, 60, voucher), Ok(60));
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(
+                1_000 + CHANNEL_CHALLENGE_PERIOD_MS,
+            );
+            let voucher = sign_voucher(&instance, 0, 70);
+            assert_eq!(instance.redeem_voucher(0, 70, voucher), Err(TipperError::ChannelExpired));
+            set_from(accts.alice);
+            assert_eq!(instance.pause(PauseScope::Withdrawals), Ok(()));
+            set_from(accts.charlie);
+            assert_eq!(instance.reclaim_channel(0), Err(TipperError::Paused));
+            set_from(accts.alice);
+            assert_eq!(instance.unpause(PauseScope::Withdrawals), Ok(()));
+            set_from(accts.charlie);
+            let before = get_balance(accts.charlie);
+            assert_eq!(instance.reclaim_channel(0), Ok(40));
+            assert_eq!(get_balance(accts.charlie), before + 40);
+            assert_eq!(instance.get_channel(0), None);
+            assert_eq!(instance.channel_escrow.get_or_default(), 0);
+        }
+
+        #[ink::test]
+        fn tip_batch_is_all_or_nothing() {
+            let accts = get_test_accts();
+            set_from(accts.alice);
+            let mut instance = Tipper::new(1, None, PRICE_PER_PIZZA, 0, accts.django);
+            assert_eq!(instance.set_max_batch_size(2), Ok(()));
+            set_from(accts.charlie);
+            assert_eq!(instance.set_max_batch_size(50), Err(TipperError::MissingRole(Role::Admin)));
+            assert_eq!(instance.tip_batch(Vec::new()), Err(TipperError::EmptyBatch));
+            let entries = vec![
+                (accts.bob, 1, "one".into()),
+                (accts.eve, 2, "two".into()),
+                (accts.frank, 3, "three".into()),
+            ];
+            assert_eq!(instance.tip_batch(entries), Err(TipperError::BatchTooLarge(2)));
+
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(20);
+            let entries = vec![(accts.bob, 1, "one".into()), (accts.eve, 2, "two".into())];
+            assert_eq!(
+                instance.tip_batch(entries.clone()),
+                Err(TipperError::InsufficientAmount(3 * PRICE_PER_PIZZA))
+            );
+            assert_eq!(instance.tip_count_of(accts.charlie), 0);
+            assert_eq!(instance.earnings_of(accts.bob), 0);
+
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(21);
+            assert_eq!(instance.tip_batch(entries), Ok(vec![0, 1]));
+            assert_eq!(instance.earnings_of(accts.bob), PRICE_PER_PIZZA);
+            assert_eq!(instance.earnings_of(accts.eve), 2 * PRICE_PER_PIZZA);
+            assert_eq!(instance.get_tip(1).unwrap().message, "two");
+
+            let recorded_events = recorded_events().collect::<Vec<_>>();
+            assert_eq!(recorded_events.len(), 2);
+            assert_expected_tip_event(&recorded_events[1], accts.eve, accts.charlie, 1, 2);
+        }
+
+        #[ink::test]
+        fn tip_messages_are_validated_and_charged_per_byte() {
+            let accts = get_test_accts();
+            set_from(accts.alice);
+            let mut instance = Tipper::new(1, None, PRICE_PER_PIZZA, 0, accts.django);
+            assert_eq!(instance.message_policy(), (DEFAULT_MAX_MESSAGE_LEN, 0));
+            assert_eq!(instance.set_message_policy(8, 2), Ok(()));
+            set_from(accts.charlie);
+            assert_eq!(
+                instance.set_message_policy(1_000, 0),
+                Err(TipperError::MissingRole(Role::Admin))
+            );
+
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(100);
+            assert_eq!(
+                instance.tip("way too long".into(), accts.bob, 1, 0, None),
+                Err(TipperError::MessageTooLong(8))
+            );
+            assert_eq!(
+                instance.tip("hi\u{7}".into(), accts.bob, 1, 0, None),
+                Err(TipperError::InvalidMessage)
+            );
+            assert_eq!(
+                instance.tip_batch(vec![(accts.bob, 1, "ok".into()), (accts.eve, 1, "\n".into())]),
+                Err(TipperError::InvalidMessage)
+            );
+
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(PRICE_PER_PIZZA);
+            assert_eq!(
+                instance.tip("hello".into(), accts.bob, 1, 0, None),
+                Err(TipperError::InsufficientAmount(PRICE_PER_PIZZA + 10))
+            );
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(
+                PRICE_PER_PIZZA + 10,
+            );
+            assert!(instance.tip("hello".into(), accts.bob, 1, 0, None).is_ok());
+            assert_eq!(instance.earnings_of(accts.bob), PRICE_PER_PIZZA);
+            assert_eq!(instance.treasury_balance(), 10);
+
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(
+                2 * PRICE_PER_PIZZA + 8,
+            );
+            let recipients = vec![(accts.bob, 1), (accts.eve, 1)];
+            assert_eq!(instance.tip_split("yo".into(), recipients, 2), Ok(0));
+            assert_eq!(instance.treasury_balance(), 18);
+        }
+
+        #[ink::test]
+        fn tips_can_be_hidden_and_moderated() {
+            let accts = get_test_accts();
+            set_from(accts.alice);
+            let mut instance = Tipper::free();
+            assert_eq!(instance.grant_role(Role::Moderator, accts.eve), Ok(()));
+            for msg in ["first", "second", "third"] {
+                assert!(instance.tip(msg.into(), accts.bob, 1, 0, None).is_ok());
+            }
+
+            set_from(accts.charlie);
+            assert_eq!(instance.hide_tip(0), Err(TipperError::NotTipRecipient));
+            assert_eq!(instance.redact_tip(0), Err(TipperError::MissingRole(Role::Moderator)));
+            set_from(accts.bob);
+            assert_eq!(instance.hide_tip(0), Ok(()));
+            assert_eq!(instance.hide_tip(0), Err(TipperError::InvalidVisibilityChange));
+            assert_eq!(instance.get_tip(0).unwrap().message, "");
+            set_from(accts.eve);
+            assert_eq!(instance.redact_tip(1), Ok(()));
+            assert_eq!(instance.delete_tip(2), Ok(()));
+            assert_eq!(instance.redact_tip(2), Err(TipperError::InvalidVisibilityChange));
+
+            let listed: Vec<(u32, String)> = instance
+                .tips_for_creator(accts.bob, 0, 10)
+                .into_iter()
+                .map(|tip| (tip.id, tip.message))
+                .collect();
+            assert_eq!(listed, vec![(1, String::new())]);
+            let deleted = instance.get_tip(2).unwrap();
+            assert_eq!((deleted.visibility, deleted.pizzas), (Visibility::Deleted, 1));
+
+            set_from(accts.bob);
+            assert_eq!(instance.hide_tip(2), Err(TipperError::InvalidVisibilityChange));
+            assert_eq!(instance.unhide_tip(0), Ok(()));
+            assert_eq!(instance.unhide_tip(0), Err(TipperError::InvalidVisibilityChange));
+            assert_eq!(instance.get_tip(0).unwrap().message, "first");
+            assert_eq!(instance.tips_for_creator(accts.bob, 0, 10).len(), 2);
+
+            let recorded_events = recorded_events().collect::<Vec<_>>();
+            let decoded_event = decode_event(recorded_events.last().unwrap());
+            assert!(matches!(
+                decoded_event,
+                Event::TipModerated(TipModerated { id: 0, visibility: Visibility::Visible, .. })
+            ));
+        }
+
+        #[ink::test]
+        fn hidden_tips_stay_hidden_when_redacted() {
+            let accts = get_test_accts();
+            set_from(accts.alice);
+            let mut instance = Tipper::free();
+            for msg in ["first", "second"] {
+                assert!(instance.tip(msg.into(), accts.bob, 1, 0, None).is_ok());
+            }
+
+            set_from(accts.bob);
+            assert_eq!(instance.hide_tip(0), Ok(()));
+            set_from(accts.alice);
+            assert_eq!(instance.redact_tip(0), Ok(()));
+            assert_eq!(instance.visibility_of(0), Visibility::Hidden);
+            assert_eq!(instance.tips_for_creator(accts.bob, 0, 10).len(), 1);
+
+            assert_eq!(instance.redact_tip(1), Ok(()));
+            set_from(accts.bob);
+            assert_eq!(instance.hide_tip(1), Ok(()));
+            assert_eq!(instance.tips_for_creator(accts.bob, 0, 10).len(), 0);
+            assert_eq!(instance.unhide_tip(0), Ok(()));
+            assert_eq!(instance.visibility_of(0), Visibility::Redacted);
+            assert_eq!(instance.get_tip(0).unwrap().message, "");
+            let listed: Vec<u32> = instance
+                .tips_for_creator(accts.bob, 0, 10)
+                .into_iter()
+                .map(|tip| tip.id)
+                .collect();
+            assert_eq!(listed, vec![0]);
+        }
+
+        #[ink::test]
+        fn tips_can_be_reported_once_per_account() {
+            let mut instance = Tipper::free();
+            tip_from_alice(&mut instance);
+            let accts = get_test_accts();
+            set_from(accts.charlie);
+            assert_eq!(instance.report_tip(7, "spam".into()), Err(TipperError::TipNotFound));
+            assert_eq!(
+                instance.report_tip(0, "spam\n".into()),
+                Err(TipperError::InvalidMessage)
+            );
+            assert_eq!(instance.report_tip(0, "spam".into()), Ok(()));
+            assert_eq!(instance.report_tip(0, "spam".into()), Err(TipperError::AlreadyReported));
+            set_from(accts.eve);
+            assert_eq!(instance.report_tip(0, "abuse".into()), Ok(()));
+            assert_eq!(instance.report_count_of(0), 2);
+        }
+
+        #[ink::test]
+        fn recipients_reply_once_per_tip() {
+            let accts = get_test_accts();
+            let mut instance = Tipper::free();
+            tip_from_alice(&mut instance);
+            assert_eq!(
+                instance.reply_to_tip(0, "thanks".into()),
+                Err(TipperError::NotTipRecipient)
+            );
+            set_from(accts.bob);
+            assert_eq!(instance.reply_to_tip(3, "thanks".into()), Err(TipperError::TipNotFound));
+            assert_eq!(
+                instance.reply_to_tip(0, "thanks\n".into()),
+                Err(TipperError::InvalidMessage)
+            );
+            assert_eq!(instance.reply_to_tip(0, "thanks!".into()), Ok(()));
+            assert_eq!(
+                instance.reply_to_tip(0, "again".into()),
+                Err(TipperError::AlreadyReplied)
+            );
+            assert_eq!(instance.get_tip(0).unwrap().reply, Some("thanks!".into()));
+            assert_eq!(
+                instance.tips_for_creator(accts.bob, 0, 1)[0].reply,
+                Some("thanks!".into())
+            );
+
+            let recorded_events = recorded_events().collect::<Vec<_>>();
+            assert_eq!(recorded_events[1].topics.len(), 3);
+            let decoded_event = decode_event(&recorded_events[1]);
+            assert!(matches!(
+                decoded_event,
+                Event::TipReplied(TipReplied { tipper, creator, id: 0, .. })
+                    if tipper == accts.alice && creator == accts.bob
+            ));
+        }
+
+        #[ink::test]
+        fn pizza_totals_and_leaderboards_are_kept() {
+            let accts = get_test_accts();
+            let mut instance = Tipper::free();
+            let tips = [
+                (accts.alice, accts.bob, 2),
+                (accts.charlie, accts.bob, 3),
+                (accts.alice, accts.eve, 4),
+                (accts.alice, accts.bob, 1),
+                (accts.django, accts.bob, 3),
+            ];
+            for (from, to, n_pizzas) in tips {
+                set_from(from);
+                assert!(instance.tip("dummy".into(), to, n_pizzas, 0, None).is_ok());
+            }
+
+            assert_eq!(
+                instance.get_pizza_tippers(),
+                vec![accts.alice, accts.charlie, accts.django]
+            );
+            assert_eq!(instance.pizzas_sent_by(accts.alice), 7);
+            assert_eq!(instance.pizzas_sent_to(accts.alice, accts.bob), 3);
+            assert_eq!(
+                instance.top_tippers_of(accts.bob),
+                vec![(accts.charlie, 3), (accts.alice, 3), (accts.django, 3)]
+            );
+            assert_eq!(instance.top_tippers_of(accts.eve), vec![(accts.alice, 4)]);
+            assert_eq!(
+                instance.top_tippers_global(),
+                vec![(accts.alice, 7), (accts.charlie, 3), (accts.django, 3)]
+            );
+        }
+
+        #[ink::test]
+        fn leaderboards_are_bounded() {
+            let mut leaderboard = Vec::new();
+            for i in 0..=LEADERBOARD_SIZE as u8 {
+                assert!(rank(&mut leaderboard, AccountId::from([i; 32]), 10 + u64::from(i)));
+            }
+            assert_eq!(leaderboard.len(), LEADERBOARD_SIZE);
+            assert_eq!(leaderboard[0].1, 10 + LEADERBOARD_SIZE as u64);
+            assert!(!rank(&mut leaderboard, AccountId::from([0xff; 32]), 1));
+            assert!(rank(&mut leaderboard, AccountId::from([1; 32]), 100));
+            assert_eq!(leaderboard[0], (AccountId::from([1; 32]), 100));
+            assert_eq!(leaderboard.len(), LEADERBOARD_SIZE);
+        }
+
+        #[ink::test]
+        fn tip_domain_needs_a_router() {
+            let accts = get_test_accts();
+            set_from(accts.alice);
+            let mut instance = Tipper::free();
+            assert_eq!(
+                instance.tip_domain("pizza.azero".into(), 1, "hi".into()),
+                Err(TipperError::ResolverUnavailable)
+            );
+            set_from(accts.bob);
+            assert_eq!(
+                instance.set_azero_router(Some(accts.eve)),
+                Err(TipperError::MissingRole(Role::Admin))
+            );
+            set_from(accts.alice);
+            assert_eq!(instance.set_azero_router(Some(accts.eve)), Ok(()));
+            assert_eq!(instance.azero_router(), Some(accts.eve));
+            assert_eq!(
+                AZERO_ID_GET_ADDRESS_SELECTOR,
+                azero_router_mock::GET_ADDRESS_SELECTOR
+            );
+        }
+
+        #[ink::test]
         fn tipper_works() {
             let tipper = Tipper::free();
-            assert_eq!(tipper.price_per_pizza, 0)
+            assert_eq!(tipper.price_per_pizza(), 0)
+        }
+        fn decode_event(event: &EmittedEvent) -> Event {
+            <Event as Decode>::decode(&mut &event.data[..])
+                .expect("invalid contract event data buffer")
         }
         fn assert_expected_tip_event(
             event: &EmittedEvent,
@@ -348,14 +3772,13 @@ mod tipper {
             expected_id: u32,
             expected_pizzas: u32,
         ) {
-            let decoded_event = <Event as Decode>::decode(&mut &event.data[..])
-                .expect("invalid contract eventy data buffer");
             if let Event::PizzaSent(PizzaSent {
                 from,
                 to,
                 id,
                 pizzas,
-            }) = decoded_event
+                ..
+            }) = decode_event(event)
             {
                 assert_eq!(from, expected_from);
                 assert_eq!(to, expected_to);
@@ -365,14 +3788,263 @@ mod tipper {
                 panic!("expected PizzaSent")
             };
         }
-        type E2EResult<T> = std::result::Result<T, Box<dyn std::error::Error>>;
         #[ink::test]
         fn tip_event_test() {
             let _tipper = Tipper::free();
             let _accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
         }
-        fn pizza_oracle_works() {
-            unimplemented!()
+
+        #[ink::test]
+        fn pizza_cost_works() {
+            let accts = get_test_accts();
+            // the default callee is alice, who is also the tipper
+            ink::env::test::set_callee::<ink::env::DefaultEnvironment>(accts.frank);
+            let mut instance = Tipper::new(1, None, PRICE_PER_PIZZA, 0, accts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(7);
+            let contract_before = get_balance(accts.frank);
+            let bob_before = get_balance(accts.bob);
+            let _tip = tip_from_alice(&mut instance);
+            assert_eq!(
+                get_balance(accts.frank),
+                contract_before,
+                "tip should not push funds out of the contract"
+            );
+            assert_eq!(get_balance(accts.bob), bob_before, "tips are pulled with withdraw");
+            assert_eq!(instance.earnings_of(accts.bob), PRICE_PER_PIZZA);
+        }
+
+        #[ink::test]
+        fn withdraw_pays_out_earnings() {
+            let accts = get_test_accts();
+            let mut instance = Tipper::new(1, None, PRICE_PER_PIZZA, 0, accts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(10);
+            set_from(accts.alice);
+            assert!(instance.tip("dummy".into(), accts.bob, 1, 3, None).is_ok());
+            let before = get_balance(accts.bob);
+            set_from(accts.bob);
+            assert_eq!(instance.withdraw(4), Ok(()));
+            assert_eq!(instance.earnings_of(accts.bob), 6);
+            assert_eq!(
+                instance.withdraw(7),
+                Err(TipperError::InsufficientEarnings(6))
+            );
+            assert_eq!(instance.withdraw_all(), Ok(6));
+            assert_eq!(instance.earnings_of(accts.bob), 0);
+            assert_eq!(get_balance(accts.bob), before + 10);
+            assert_eq!(instance.withdraw_all(), Err(TipperError::NoEarnings));
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "")]
+        fn pizza_cost_fails() {
+            let accts = get_test_accts();
+            let mut instance = Tipper::new(1, None, PRICE_PER_PIZZA, 0, accts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(6);
+            let before = get_balance(accts.alice);
+            ink::env::debug_println!("before value: {}", before);
+            let _tip = tip_from_alice(&mut instance);
+            let after = get_balance(accts.alice);
+            ink::env::debug_println!("after value: {}", after);
+        }
+    }
+
+    #[cfg(all(test, feature = "e2e-tests"))]
+    mod e2e_tests {
+        use super::*;
+        use ink_e2e::build_message;
+        use ink_e2e::subxt::tx::Signer;
+
+        type E2EResult<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+        #[ink_e2e::test(additional_contracts = "../highlighted_pizzas/Cargo.toml")]
+        async fn new_instantiates_highlighted_pizzas(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            let code_hash = client
+                .upload("highlighted_pizzas", &ink_e2e::alice(), None)
+                .await
+                .expect("upload failed")
+                .code_hash;
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let tipper = TipperRef::new(1, Some(code_hash), 1, 0, accounts.django);
+            let contract_acc_id = client
+                .instantiate("pizza_tipper", &ink_e2e::alice(), tipper, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let get = build_message::<TipperRef>(contract_acc_id)
+                .call(|tipper| tipper.highlighted_pizzas());
+            let get_res = client.call_dry_run(&ink_e2e::alice(), &get, 0, None).await;
+            assert!(get_res.return_value().is_some());
+            Ok(())
+        }
+
+        #[ink_e2e::test(additional_contracts = "../highlighted_pizzas/Cargo.toml")]
+        async fn highlight_my_tip_credits_treasury(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            let code_hash = client
+                .upload("highlighted_pizzas", &ink_e2e::alice(), None)
+                .await
+                .expect("upload failed")
+                .code_hash;
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let tipper = TipperRef::new(1, Some(code_hash), 0, 0, accounts.django);
+            let contract_acc_id = client
+                .instantiate("pizza_tipper", &ink_e2e::alice(), tipper, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let set_price = build_message::<TipperRef>(contract_acc_id)
+                .call(|tipper| tipper.set_highlight_price(10));
+            client
+                .call(&ink_e2e::alice(), set_price, 0, None)
+                .await
+                .expect("set_highlight_price failed");
+            let tip = build_message::<TipperRef>(contract_acc_id)
+                .call(|tipper| tipper.tip("dummy".to_string(), accounts.eve, 1, 0, None));
+            client
+                .call(&ink_e2e::bob(), tip, 0, None)
+                .await
+                .expect("tip failed");
+
+            let highlight = build_message::<TipperRef>(contract_acc_id)
+                .call(|tipper| tipper.highlight_my_tip(0));
+            client
+                .call(&ink_e2e::bob(), highlight, 15, None)
+                .await
+                .expect("highlight_my_tip failed");
+            let balance = build_message::<TipperRef>(contract_acc_id)
+                .call(|tipper| tipper.treasury_balance());
+            let balance_res = client.call_dry_run(&ink_e2e::bob(), &balance, 0, None).await;
+            assert_eq!(balance_res.return_value(), 10);
+            Ok(())
+        }
+
+        #[ink_e2e::test(additional_contracts = "../highlighted_pizzas/Cargo.toml")]
+        async fn unhighlight_my_tip_reports_missing_highlight(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            let code_hash = client
+                .upload("highlighted_pizzas", &ink_e2e::alice(), None)
+                .await
+                .expect("upload failed")
+                .code_hash;
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let tipper = TipperRef::new(1, Some(code_hash), 0, 0, accounts.django);
+            let contract_acc_id = client
+                .instantiate("pizza_tipper", &ink_e2e::alice(), tipper, 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let tip = build_message::<TipperRef>(contract_acc_id)
+                .call(|tipper| tipper.tip("dummy".to_string(), accounts.eve, 1, 0, None));
+            client
+                .call(&ink_e2e::bob(), tip, 0, None)
+                .await
+                .expect("tip failed");
+
+            let unhighlight = build_message::<TipperRef>(contract_acc_id)
+                .call(|tipper| tipper.unhighlight_my_tip(0));
+            let unhighlight_res = client
+                .call_dry_run(&ink_e2e::bob(), &unhighlight, 0, None)
+                .await;
+            assert_eq!(
+                unhighlight_res.return_value(),
+                Err(TipperError::HighlightError(
+                    HighlightedPizzasError::HighlightNotFound
+                ))
+            );
+
+            let highlight = build_message::<TipperRef>(contract_acc_id)
+                .call(|tipper| tipper.highlight_my_tip(0));
+            client
+                .call(&ink_e2e::bob(), highlight, 0, None)
+                .await
+                .expect("highlight_my_tip failed");
+            let unhighlight_res = client
+                .call_dry_run(&ink_e2e::bob(), &unhighlight, 0, None)
+                .await;
+            assert_eq!(unhighlight_res.return_value(), Ok(()));
+            Ok(())
+        }
+
+        #[ink_e2e::test(additional_contracts = "../highlighted_pizzas/Cargo.toml")]
+        async fn set_highlighted_pizzas_rejects_foreign_deployment(
+            mut client: ink_e2e::Client<C, E>,
+        ) -> E2EResult<()> {
+            let foreign_acc_id = client
+                .instantiate(
+                    "highlighted_pizzas",
+                    &ink_e2e::alice(),
+                    HighlightedPizzasRef::new(),
+                    0,
+                    None,
+                )
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let contract_acc_id = client
+                .instantiate("pizza_tipper", &ink_e2e::alice(), TipperRef::free(), 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let set = build_message::<TipperRef>(contract_acc_id)
+                .call(|tipper| tipper.set_highlighted_pizzas(Some(foreign_acc_id)));
+            let set_res = client.call_dry_run(&ink_e2e::alice(), &set, 0, None).await;
+            assert_eq!(
+                set_res.return_value(),
+                Err(TipperError::HighlightError(
+                    HighlightedPizzasError::AccessDenied
+                ))
+            );
+            Ok(())
+        }
+
+        #[ink_e2e::test(additional_contracts = "../azero_router_mock/Cargo.toml")]
+        async fn tip_domain_tips_domain_owner(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+            use azero_router_mock::AzeroRouterMockRef;
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let router_acc_id = client
+                .instantiate("azero_router_mock", &ink_e2e::alice(), AzeroRouterMockRef::new(), 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let register = build_message::<AzeroRouterMockRef>(router_acc_id)
+                .call(|router| router.register("pizza.azero".into(), accounts.eve));
+            client
+                .call(&ink_e2e::alice(), register, 0, None)
+                .await
+                .expect("register failed");
+
+            let contract_acc_id = client
+                .instantiate("pizza_tipper", &ink_e2e::alice(), TipperRef::free(), 0, None)
+                .await
+                .expect("instantiate failed")
+                .account_id;
+            let set_router = build_message::<TipperRef>(contract_acc_id)
+                .call(|tipper| tipper.set_azero_router(Some(router_acc_id)));
+            client
+                .call(&ink_e2e::alice(), set_router, 0, None)
+                .await
+                .expect("set_azero_router failed");
+
+            let tip = build_message::<TipperRef>(contract_acc_id)
+                .call(|tipper| tipper.tip_domain("pizza.azero".into(), 1, "hi".into()));
+            client
+                .call(&ink_e2e::bob(), tip, 0, None)
+                .await
+                .expect("tip_domain failed");
+            let latest = build_message::<TipperRef>(contract_acc_id)
+                .call(|tipper| tipper.latest_tip_of(get_bob()));
+            let latest_res = client.call_dry_run(&ink_e2e::bob(), &latest, 0, None).await;
+            assert_eq!(latest_res.return_value().map(|tip| tip.to), Some(accounts.eve));
+
+            let unknown = build_message::<TipperRef>(contract_acc_id)
+                .call(|tipper| tipper.tip_domain("pasta.azero".into(), 1, "hi".into()));
+            let unknown_res = client.call_dry_run(&ink_e2e::bob(), &unknown, 0, None).await;
+            assert_eq!(unknown_res.return_value(), Err(TipperError::DoesntExist));
+            Ok(())
         }
 
         fn get_bob() -> AccountId {
@@ -381,7 +4053,7 @@ mod tipper {
             bob_acct_id
         }
         #[ink_e2e::test]
-        fn get_tippers_works(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
+        async fn get_tippers_works(mut client: ink_e2e::Client<C, E>) -> E2EResult<()> {
             let tipper = TipperRef::free();
             let contract_acc_id = client
                 .instantiate("pizza_tipper", &ink_e2e::alice(), tipper, 0, None)
@@ -389,7 +4061,10 @@ mod tipper {
                 .expect("instantiate failed")
                 .account_id;
             let tip = build_message::<TipperRef>(contract_acc_id)
-                .call(|tipper| tipper.tip("dummy".to_string(), get_test_accts().eve, 1));
+                .call(|tipper| {
+                    let eve = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>().eve;
+                    tipper.tip("dummy".to_string(), eve, 1, 0, None)
+                });
             let _tip_res = client
                 .call(&ink_e2e::bob(), tip, 0, None)
                 .await
@@ -402,34 +4077,6 @@ mod tipper {
             assert!(&get_res.return_value().contains(&bob_acct_id));
             Ok(())
         }
-        #[ink::test]
-        fn pizza_cost_works() {
-            let accts = get_test_accts();
-            let dummy_hash: Hash = Hash::from([0x00; 32]);
-            let mut instance = Tipper::new(1, dummy_hash, PRICE_PER_PIZZA, );
-            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(7);
-            let before = get_balance(accts.alice);
-            ink::env::debug_println!("before value: {}", before);
-            let _tip = tip_from_alice(&mut instance);
-            let after = get_balance(accts.alice);
-            ink::env::debug_println!("after value: {}", after);
-            let expected_balance = before - PRICE_PER_PIZZA;
-            assert_eq!(after, expected_balance, "Balance is incorrect");
-        }
-
-        #[ink::test]
-        #[should_panic(expected = "")]
-        fn pizza_cost_fails() {
-            let accts = get_test_accts();
-            let dummy_hash: Hash = Hash::from([0x00; 32]);
-            let mut instance = Tipper::new(1, dummy_hash, PRICE_PER_PIZZA, );
-            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(6);
-            let before = get_balance(accts.alice);
-            ink::env::debug_println!("before value: {}", before);
-            let _tip = tip_from_alice(&mut instance);
-            let after = get_balance(accts.alice);
-            ink::env::debug_println!("after value: {}", after);
-        }
     }
 }
 // impl fmt::Display for tipper::Error {
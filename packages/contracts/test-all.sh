@@ -2,7 +2,7 @@
 
 # NOTE: Add contracts to this array to test them ⬇️
 # IMPORTANT: Just use spaces (_no commas_) between multiple array items (it's a bash convention).
-contracts=( "pizza_tipper" "highlighted_pizzas" )
+contracts=( "pizza_tipper" "highlighted_pizzas" "azero_router_mock" )
 
 for i in "${contracts[@]}"
 do